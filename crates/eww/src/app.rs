@@ -30,7 +30,7 @@ use tokio::sync::mpsc::UnboundedSender;
 use yuck::{
     config::{
         monitor::MonitorIdentifier,
-        script_var_definition::ScriptVarDefinition,
+        script_var_definition::{DbusBus, ScriptVarDefinition},
         window_geometry::{AnchorPoint, WindowGeometry},
     },
     error::DiagError,
@@ -45,6 +45,8 @@ use yuck::{
 pub enum DaemonCommand {
     NoOp,
     UpdateVars(Vec<(VarName, DynVal)>),
+    /// Set a variable to the result of evaluating an expression in the global scope, i.e. the `:error-value` of a `defpoll`.
+    UpdateVarFromExpr(VarName, SimplExpr),
     ReloadConfigAndCss(DaemonResponseSender),
     OpenInspector,
     OpenMany {
@@ -83,6 +85,30 @@ pub enum DaemonCommand {
     PrintGraph(DaemonResponseSender),
     ListWindows(DaemonResponseSender),
     ListActiveWindows(DaemonResponseSender),
+    DismissNotifications {
+        ids: Vec<u32>,
+        all: bool,
+        sender: DaemonResponseSender,
+    },
+    InvokeNotificationAction {
+        id: u32,
+        action: String,
+        sender: DaemonResponseSender,
+    },
+    SetPowerProfile {
+        profile: String,
+        sender: DaemonResponseSender,
+    },
+    CallDbus {
+        bus: DbusBus,
+        destination: String,
+        path: String,
+        interface: String,
+        method: String,
+        signature: Option<String>,
+        args: Vec<String>,
+        sender: DaemonResponseSender,
+    },
 }
 
 /// An opened window.
@@ -167,6 +193,16 @@ impl<B: DisplayBackend> App<B> {
                     self.update_global_variable(var_name, new_value);
                 }
             }
+            DaemonCommand::UpdateVarFromExpr(var_name, expr) => {
+                let result = {
+                    let scope_graph = self.scope_graph.borrow();
+                    scope_graph.evaluate_simplexpr_in_scope(scope_graph.root_index, &expr)
+                };
+                match result {
+                    Ok(value) => self.update_global_variable(var_name, value),
+                    Err(err) => error_handling_ctx::print_error(err),
+                }
+            }
             DaemonCommand::ReloadConfigAndCss(sender) => {
                 let mut errors = Vec::new();
 
@@ -287,6 +323,39 @@ impl<B: DisplayBackend> App<B> {
                 sender.send_success(output)?
             }
             DaemonCommand::PrintGraph(sender) => sender.send_success(self.scope_graph.borrow().visualize())?,
+            DaemonCommand::DismissNotifications { ids, all, sender } => {
+                tokio::spawn(async move {
+                    let result = notification_server::dismiss(ids, all).await;
+                    crate::print_result_err!("while responding to dismiss-notifications", sender.respond_with_result(result));
+                });
+            }
+            DaemonCommand::InvokeNotificationAction { id, action, sender } => {
+                tokio::spawn(async move {
+                    let result = notification_server::invoke_action(id, &action).await;
+                    crate::print_result_err!(
+                        "while responding to invoke-notification-action",
+                        sender.respond_with_result(result)
+                    );
+                });
+            }
+            DaemonCommand::SetPowerProfile { profile, sender } => {
+                tokio::spawn(async move {
+                    let result = config::upower::set_power_profile(&profile).await;
+                    crate::print_result_err!("while responding to set-power-profile", sender.respond_with_result(result));
+                });
+            }
+            DaemonCommand::CallDbus { bus, destination, path, interface, method, signature, args, sender } => {
+                tokio::spawn(async move {
+                    let result =
+                        config::dbus_var::call_method(bus, &destination, &path, &interface, &method, signature.as_deref(), &args)
+                            .await;
+                    let response = match result {
+                        Ok(reply) => sender.send_success(reply),
+                        Err(e) => sender.respond_with_result::<()>(Err(e)),
+                    };
+                    crate::print_result_err!("while responding to call-dbus", response);
+                });
+            }
         }
         Ok(())
     }
@@ -483,6 +552,7 @@ impl<B: DisplayBackend> App<B> {
 
         self.eww_config = config;
         self.scope_graph.borrow_mut().clear(self.eww_config.generate_initial_state()?);
+        notification_server::configure(self.eww_config.get_notification_server().cloned(), self.app_evt_send.clone());
 
         let open_window_ids: Vec<String> =
             self.open_windows.keys().cloned().chain(self.failed_windows.iter().cloned()).dedup().collect();
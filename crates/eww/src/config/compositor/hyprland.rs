@@ -0,0 +1,232 @@
+//! Hyprland's IPC consists of two sockets: requests are written to `.socket.sock`, using a new connection per request,
+//! while events are streamed from `.socket2.sock` line by line, formatted as `EVENT>>DATA`.
+
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use serde::de::DeserializeOwned;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::UnixStream,
+};
+
+use super::{ActiveWindow, State, Workspace};
+
+/// Events after which the workspaces need to be requested again.
+/// Newer versions of hyprland send a `v2` variant along with most of these, which are ignored to not refresh twice.
+const WORKSPACE_EVENTS: &[&str] = &[
+    "workspace",
+    "focusedmon",
+    "createworkspace",
+    "destroyworkspace",
+    "moveworkspace",
+    "renameworkspace",
+    "monitoradded",
+    "monitorremoved",
+    "openwindow",
+    "closewindow",
+    "movewindow",
+];
+
+/// Events after which the active window needs to be requested again.
+/// `windowtitle` is sent for any window, not just the active one.
+const WINDOW_EVENTS: &[&str] = &["activewindow", "windowtitle"];
+
+#[derive(serde::Deserialize)]
+struct HyprlandWorkspace {
+    id: i64,
+    name: String,
+    monitor: String,
+    windows: u64,
+}
+
+#[derive(serde::Deserialize)]
+struct WorkspaceRef {
+    id: i64,
+}
+
+#[derive(serde::Deserialize)]
+struct Client {
+    address: String,
+    workspace: WorkspaceRef,
+}
+
+/// The directory containing the sockets of the hyprland instance with the given signature.
+pub(super) fn socket_dir(signature: &str) -> PathBuf {
+    // hyprland 0.40 moved its sockets from /tmp into the runtime dir
+    match std::env::var_os("XDG_RUNTIME_DIR").map(|dir| PathBuf::from(dir).join("hypr").join(signature)) {
+        Some(dir) if dir.exists() => dir,
+        _ => PathBuf::from("/tmp/hypr").join(signature),
+    }
+}
+
+pub(super) async fn run(dir: &Path, mut on_change: impl FnMut(&State)) -> Result<()> {
+    // connect to the events before reading the initial state, to not miss anything in between
+    let events = UnixStream::connect(dir.join(".socket2.sock"))
+        .await
+        .with_context(|| format!("Failed to connect to the hyprland event socket in {}", dir.display()))?;
+    let mut events = BufReader::new(events).lines();
+
+    // hyprland only tells us when a window becomes urgent, so we need to remember which ones are until they get focused
+    let mut urgent_windows = HashSet::new();
+    let mut state = State { workspaces: workspaces(dir, &urgent_windows).await?, active_window: active_window(dir).await? };
+    on_change(&state);
+
+    while let Some(line) = events.next_line().await? {
+        let Some((event, data)) = line.split_once(">>") else {
+            continue;
+        };
+        let refresh_workspaces = match event {
+            "urgent" => urgent_windows.insert(data.to_string()),
+            "activewindowv2" => urgent_windows.remove(data),
+            "closewindow" => {
+                urgent_windows.remove(data);
+                true
+            }
+            _ => WORKSPACE_EVENTS.contains(&event),
+        };
+        let refresh_window = WINDOW_EVENTS.contains(&event);
+        if refresh_workspaces {
+            state.workspaces = workspaces(dir, &urgent_windows).await?;
+        }
+        if refresh_window {
+            state.active_window = active_window(dir).await?;
+        }
+        if refresh_workspaces || refresh_window {
+            on_change(&state);
+        }
+    }
+    Ok(())
+}
+
+async fn workspaces(dir: &Path, urgent_windows: &HashSet<String>) -> Result<Vec<Workspace>> {
+    let workspaces: Vec<HyprlandWorkspace> = request(dir, "workspaces").await?;
+    let active: WorkspaceRef = request(dir, "activeworkspace").await?;
+    let urgent_workspaces: HashSet<i64> = if urgent_windows.is_empty() {
+        HashSet::new()
+    } else {
+        // addresses are prefixed with 0x in requests, but not in events
+        let clients: Vec<Client> = request(dir, "clients").await?;
+        clients
+            .into_iter()
+            .filter(|client| urgent_windows.contains(client.address.trim_start_matches("0x")))
+            .map(|client| client.workspace.id)
+            .collect()
+    };
+
+    let mut workspaces: Vec<Workspace> = workspaces
+        .into_iter()
+        .map(|workspace| Workspace {
+            focused: workspace.id == active.id,
+            urgent: urgent_workspaces.contains(&workspace.id),
+            id: workspace.id,
+            name: workspace.name,
+            monitor: workspace.monitor,
+            windows: workspace.windows,
+        })
+        .collect();
+    workspaces.sort_by_key(|workspace| workspace.id);
+    Ok(workspaces)
+}
+
+async fn active_window(dir: &Path) -> Result<ActiveWindow> {
+    #[derive(serde::Deserialize)]
+    struct Window {
+        // hyprland responds with an empty object if no window is focused
+        #[serde(default)]
+        title: String,
+        #[serde(default)]
+        class: String,
+    }
+    let window: Window = request(dir, "activewindow").await?;
+    Ok(ActiveWindow { title: window.title, class: window.class })
+}
+
+/// Send a request for JSON output to hyprland, i.e. `workspaces`.
+async fn request<T: DeserializeOwned>(dir: &Path, command: &str) -> Result<T> {
+    let mut stream = UnixStream::connect(dir.join(".socket.sock"))
+        .await
+        .with_context(|| format!("Failed to connect to the hyprland socket in {}", dir.display()))?;
+    stream.write_all(format!("j/{}", command).as_bytes()).await?;
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await?;
+    serde_json::from_slice(&response).with_context(|| format!("Invalid response from hyprland to `{}`", command))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::temp_dir;
+    use std::{
+        collections::HashMap,
+        sync::{Arc, Mutex},
+    };
+    use tokio::net::UnixListener;
+
+    type Responses = Arc<Mutex<HashMap<&'static str, &'static str>>>;
+
+    /// Answer requests on the `.socket.sock` in `dir` with the responses for their command.
+    fn serve_requests(dir: &Path, responses: Responses) {
+        let listener = UnixListener::bind(dir.join(".socket.sock")).unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut request = [0; 64];
+                let len = stream.read(&mut request).await.unwrap();
+                let command = std::str::from_utf8(&request[..len]).unwrap().trim_start_matches("j/");
+                let response = responses.lock().unwrap()[command];
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+    }
+
+    #[tokio::test]
+    async fn test_hyprland() {
+        let dir = temp_dir("hyprland");
+        let responses: Responses = Arc::new(Mutex::new(HashMap::from([
+            (
+                "workspaces",
+                r#"[{"id":2,"name":"2","monitor":"DP-1","windows":1},{"id":1,"name":"web","monitor":"DP-2","windows":2}]"#,
+            ),
+            ("activeworkspace", r#"{"id":1,"name":"web"}"#),
+            ("activewindow", r#"{"address":"0x1","title":"x","class":"kitty"}"#),
+            ("clients", r#"[{"address":"0xabc","workspace":{"id":2,"name":"2"}}]"#),
+        ])));
+        serve_requests(&dir, responses.clone());
+        let events = UnixListener::bind(dir.join(".socket2.sock")).unwrap();
+
+        let (send, mut recv) = tokio::sync::mpsc::unbounded_channel();
+        let run_dir = dir.clone();
+        tokio::spawn(async move { run(&run_dir, |state| send.send(state.clone()).unwrap()).await.unwrap() });
+        let (mut events, _) = events.accept().await.unwrap();
+
+        let state = recv.recv().await.unwrap();
+        assert_eq!(
+            state.workspaces,
+            vec![
+                Workspace { id: 1, name: "web".into(), monitor: "DP-2".into(), windows: 2, focused: true, urgent: false },
+                Workspace { id: 2, name: "2".into(), monitor: "DP-1".into(), windows: 1, focused: false, urgent: false },
+            ]
+        );
+        assert_eq!(state.active_window, ActiveWindow { title: "x".into(), class: "kitty".into() });
+
+        responses.lock().unwrap().insert("activeworkspace", r#"{"id":2,"name":"2"}"#);
+        events.write_all(b"workspacev2>>2,2\nworkspace>>2\n").await.unwrap();
+        let state = recv.recv().await.unwrap();
+        assert!(state.workspaces[1].focused && !state.workspaces[0].focused);
+
+        events.write_all(b"urgent>>abc\n").await.unwrap();
+        assert!(recv.recv().await.unwrap().workspaces[1].urgent);
+
+        responses.lock().unwrap().insert("activewindow", r#"{}"#);
+        events.write_all(b"activewindow>>,\n").await.unwrap();
+        assert_eq!(recv.recv().await.unwrap().active_window, ActiveWindow::default());
+
+        events.write_all(b"activewindowv2>>abc\n").await.unwrap();
+        assert!(!recv.recv().await.unwrap().workspaces[1].urgent);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
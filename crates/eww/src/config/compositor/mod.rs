@@ -0,0 +1,86 @@
+//! Tracking of the workspaces and the focused window of wayland compositors through their IPC sockets,
+//! used for the `EWW_WORKSPACES` and `EWW_ACTIVE_WINDOW` magic variables.
+//!
+//! Both variables are served by the same listeners, which report the full [`State`] on every relevant event.
+//! Values are only sent to eww when the part of the state a variable is interested in actually changed.
+
+use std::path::PathBuf;
+
+use anyhow::{Result, anyhow};
+use simplexpr::dynval::DynVal;
+use yuck::config::script_var_definition::ListenVarSender;
+
+mod hyprland;
+mod sway;
+
+#[derive(Debug, Default, Clone, PartialEq, serde::Serialize)]
+struct Workspace {
+    id: i64,
+    name: String,
+    monitor: String,
+    /// Amount of windows on the workspace
+    windows: u64,
+    focused: bool,
+    urgent: bool,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, serde::Serialize)]
+struct ActiveWindow {
+    title: String,
+    /// The window class, or the app id for native wayland windows on sway
+    class: String,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+struct State {
+    workspaces: Vec<Workspace>,
+    active_window: ActiveWindow,
+}
+
+enum Compositor {
+    /// Hyprland, with the directory containing its sockets
+    Hyprland(PathBuf),
+    /// Sway (or i3, which speaks the same protocol), with the path of its IPC socket
+    Sway(PathBuf),
+}
+
+impl Compositor {
+    fn detect() -> Result<Self> {
+        if let Ok(signature) = std::env::var("HYPRLAND_INSTANCE_SIGNATURE") {
+            return Ok(Compositor::Hyprland(hyprland::socket_dir(&signature)));
+        }
+        if let Ok(socket) = std::env::var("SWAYSOCK").or_else(|_| std::env::var("I3SOCK")) {
+            return Ok(Compositor::Sway(PathBuf::from(socket)));
+        }
+        Err(anyhow!("No supported compositor found: neither HYPRLAND_INSTANCE_SIGNATURE nor SWAYSOCK is set"))
+    }
+
+    async fn run(&self, on_change: impl FnMut(&State)) -> Result<()> {
+        match self {
+            Compositor::Hyprland(dir) => hyprland::run(dir, on_change).await,
+            Compositor::Sway(socket) => sway::run(socket, on_change).await,
+        }
+    }
+}
+
+pub async fn listen_workspaces(send: ListenVarSender) -> Result<(), Box<dyn std::error::Error + Sync + Send + 'static>> {
+    Ok(listen(send, |state| serde_json::to_string(&state.workspaces).unwrap()).await?)
+}
+
+pub async fn listen_active_window(send: ListenVarSender) -> Result<(), Box<dyn std::error::Error + Sync + Send + 'static>> {
+    Ok(listen(send, |state| serde_json::to_string(&state.active_window).unwrap()).await?)
+}
+
+async fn listen(send: ListenVarSender, to_json: fn(&State) -> String) -> Result<()> {
+    let compositor = Compositor::detect()?;
+    let mut last_value = None;
+    compositor
+        .run(|state| {
+            let value = to_json(state);
+            if last_value.as_ref() != Some(&value) {
+                send(DynVal::from(value.clone()));
+                last_value = Some(value);
+            }
+        })
+        .await
+}
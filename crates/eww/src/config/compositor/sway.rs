@@ -0,0 +1,254 @@
+//! The sway (and i3) IPC protocol: every message consists of the magic string `i3-ipc`, the length and type of its payload
+//! as 32 bit integers in native byte order, and a JSON payload. Events are messages with the highest bit of the type set.
+
+use std::{collections::HashMap, path::Path};
+
+use anyhow::{Context, Result, bail};
+use serde::de::DeserializeOwned;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::UnixStream,
+};
+
+use super::{ActiveWindow, State, Workspace};
+
+const MAGIC: &[u8] = b"i3-ipc";
+const GET_WORKSPACES: u32 = 1;
+const SUBSCRIBE: u32 = 2;
+const GET_TREE: u32 = 4;
+const EVENT_BIT: u32 = 1 << 31;
+
+#[derive(serde::Deserialize)]
+struct SwayWorkspace {
+    num: i64,
+    name: String,
+    output: String,
+    focused: bool,
+    urgent: bool,
+}
+
+/// A node of the layout tree, as returned by `GET_TREE`.
+#[derive(serde::Deserialize)]
+struct Node {
+    #[serde(rename = "type")]
+    kind: String,
+    name: Option<String>,
+    #[serde(default)]
+    focused: bool,
+    /// Only set for native wayland windows
+    app_id: Option<String>,
+    /// Only set for X11 windows
+    window_properties: Option<WindowProperties>,
+    #[serde(default)]
+    nodes: Vec<Node>,
+    #[serde(default)]
+    floating_nodes: Vec<Node>,
+}
+
+#[derive(serde::Deserialize)]
+struct WindowProperties {
+    class: Option<String>,
+}
+
+impl Node {
+    fn children(&self) -> impl Iterator<Item = &Node> {
+        self.nodes.iter().chain(self.floating_nodes.iter())
+    }
+
+    fn is_window(&self) -> bool {
+        matches!(self.kind.as_str(), "con" | "floating_con") && self.children().next().is_none()
+    }
+
+    fn window_count(&self) -> u64 {
+        if self.is_window() { 1 } else { self.children().map(Node::window_count).sum() }
+    }
+
+    fn focused_window(&self) -> Option<&Node> {
+        if self.focused && self.is_window() { Some(self) } else { self.children().find_map(Node::focused_window) }
+    }
+
+    fn collect_workspaces<'a>(&'a self, workspaces: &mut Vec<&'a Node>) {
+        if self.kind == "workspace" {
+            workspaces.push(self);
+        } else {
+            self.children().for_each(|child| child.collect_workspaces(workspaces));
+        }
+    }
+}
+
+pub(super) async fn run(socket: &Path, mut on_change: impl FnMut(&State)) -> Result<()> {
+    let connect = || async {
+        UnixStream::connect(socket).await.with_context(|| format!("Failed to connect to the sway socket {}", socket.display()))
+    };
+    // subscribe to events before reading the initial state, to not miss anything in between
+    let mut events = connect().await?;
+    send_message(&mut events, SUBSCRIBE, br#"["workspace","window"]"#).await?;
+    let reply: serde_json::Value = read_reply(&mut events, SUBSCRIBE).await?;
+    if reply["success"] != true {
+        bail!("Failed to subscribe to sway events: {}", reply);
+    }
+
+    let mut requests = connect().await?;
+    on_change(&read_state(&mut requests).await?);
+    while let Some((kind, _)) = read_message(&mut events).await? {
+        // the events only contain the changed parts, so it's easier to just read everything again
+        if kind & EVENT_BIT != 0 {
+            on_change(&read_state(&mut requests).await?);
+        }
+    }
+    Ok(())
+}
+
+async fn read_state(stream: &mut UnixStream) -> Result<State> {
+    let workspaces: Vec<SwayWorkspace> = request(stream, GET_WORKSPACES).await?;
+    let tree: Node = request(stream, GET_TREE).await?;
+
+    let mut workspace_nodes = Vec::new();
+    tree.collect_workspaces(&mut workspace_nodes);
+    let window_counts: HashMap<&str, u64> =
+        workspace_nodes.into_iter().filter_map(|node| Some((node.name.as_deref()?, node.window_count()))).collect();
+    let workspaces = workspaces
+        .into_iter()
+        .map(|workspace| Workspace {
+            id: workspace.num,
+            windows: window_counts.get(workspace.name.as_str()).copied().unwrap_or_default(),
+            name: workspace.name,
+            monitor: workspace.output,
+            focused: workspace.focused,
+            urgent: workspace.urgent,
+        })
+        .collect();
+
+    let active_window = tree
+        .focused_window()
+        .map(|window| ActiveWindow {
+            title: window.name.clone().unwrap_or_default(),
+            class: window.app_id.clone().or_else(|| window.window_properties.as_ref()?.class.clone()).unwrap_or_default(),
+        })
+        .unwrap_or_default();
+    Ok(State { workspaces, active_window })
+}
+
+async fn request<T: DeserializeOwned>(stream: &mut UnixStream, kind: u32) -> Result<T> {
+    send_message(stream, kind, &[]).await?;
+    read_reply(stream, kind).await
+}
+
+async fn read_reply<T: DeserializeOwned>(stream: &mut UnixStream, kind: u32) -> Result<T> {
+    match read_message(stream).await? {
+        Some((reply_kind, payload)) if reply_kind == kind => serde_json::from_slice(&payload).context("Invalid reply from sway"),
+        Some((reply_kind, _)) => bail!("Expected a reply of type {} from sway, but got {}", kind, reply_kind),
+        None => bail!("Sway closed the connection"),
+    }
+}
+
+async fn send_message(stream: &mut UnixStream, kind: u32, payload: &[u8]) -> Result<()> {
+    let mut message = Vec::with_capacity(MAGIC.len() + 8 + payload.len());
+    message.extend_from_slice(MAGIC);
+    message.extend_from_slice(&(payload.len() as u32).to_ne_bytes());
+    message.extend_from_slice(&kind.to_ne_bytes());
+    message.extend_from_slice(payload);
+    Ok(stream.write_all(&message).await?)
+}
+
+/// Read the next message, returning its type and payload, or `None` if the connection was closed.
+async fn read_message(stream: &mut UnixStream) -> Result<Option<(u32, Vec<u8>)>> {
+    let mut header = [0; 14];
+    match stream.read_exact(&mut header).await {
+        Ok(_) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    }
+    if &header[..MAGIC.len()] != MAGIC {
+        bail!("Invalid message from sway");
+    }
+    let len = u32::from_ne_bytes(header[6..10].try_into().unwrap());
+    let kind = u32::from_ne_bytes(header[10..14].try_into().unwrap());
+    let mut payload = vec![0; len as usize];
+    stream.read_exact(&mut payload).await?;
+    Ok(Some((kind, payload)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::temp_dir;
+    use std::sync::{Arc, Mutex};
+    use tokio::{net::UnixListener, sync::mpsc::UnboundedReceiver};
+
+    const WORKSPACES: &str = r#"[
+        {"num":1,"name":"1","output":"DP-1","focused":true,"urgent":false,"visible":true},
+        {"num":2,"name":"2","output":"DP-1","focused":false,"urgent":true,"visible":false}
+    ]"#;
+    const TREE: &str = r#"{"type":"root","name":"root","nodes":[
+        {"type":"output","name":"__i3","nodes":[{"type":"workspace","name":"__i3_scratch","nodes":[{"type":"con","name":"x"}]}]},
+        {"type":"output","name":"DP-1","nodes":[
+            {"type":"workspace","name":"1","nodes":[
+                {"type":"con","name":"vim","focused":true,"app_id":"foot"},
+                {"type":"con","name":null,"nodes":[
+                    {"type":"con","name":"Firefox","app_id":null,"window_properties":{"class":"firefox"}}
+                ]}
+            ],"floating_nodes":[{"type":"floating_con","name":"calc","app_id":"calc"}]},
+            {"type":"workspace","name":"2","nodes":[]}
+        ]}
+    ]}"#;
+
+    /// Answer requests on `socket` with the current tree, and forward events to the subscribed connection.
+    fn serve(socket: &Path, tree: Arc<Mutex<String>>, events: UnboundedReceiver<u32>) {
+        let listener = UnixListener::bind(socket).unwrap();
+        let events = Arc::new(tokio::sync::Mutex::new(Some(events)));
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let (tree, events) = (tree.clone(), events.clone());
+                tokio::spawn(async move {
+                    while let Some((kind, _)) = read_message(&mut stream).await.unwrap() {
+                        let reply = match kind {
+                            GET_WORKSPACES => WORKSPACES.to_string(),
+                            GET_TREE => tree.lock().unwrap().clone(),
+                            SUBSCRIBE => {
+                                send_message(&mut stream, SUBSCRIBE, br#"{"success":true}"#).await.unwrap();
+                                let mut events = events.lock().await.take().unwrap();
+                                while let Some(event) = events.recv().await {
+                                    send_message(&mut stream, EVENT_BIT | event, b"{}").await.unwrap();
+                                }
+                                return;
+                            }
+                            _ => unreachable!(),
+                        };
+                        send_message(&mut stream, kind, reply.as_bytes()).await.unwrap();
+                    }
+                });
+            }
+        });
+    }
+
+    #[tokio::test]
+    async fn test_sway() {
+        let dir = temp_dir("sway");
+        let socket = dir.join("sway-ipc.sock");
+        let tree = Arc::new(Mutex::new(TREE.to_string()));
+        let (event_send, event_recv) = tokio::sync::mpsc::unbounded_channel();
+        serve(&socket, tree.clone(), event_recv);
+
+        let (send, mut recv) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move { run(&socket, |state| send.send(state.clone()).unwrap()).await.unwrap() });
+
+        let state = recv.recv().await.unwrap();
+        assert_eq!(
+            state.workspaces,
+            vec![
+                Workspace { id: 1, name: "1".into(), monitor: "DP-1".into(), windows: 3, focused: true, urgent: false },
+                Workspace { id: 2, name: "2".into(), monitor: "DP-1".into(), windows: 0, focused: false, urgent: true },
+            ]
+        );
+        assert_eq!(state.active_window, ActiveWindow { title: "vim".into(), class: "foot".into() });
+
+        *tree.lock().unwrap() =
+            TREE.replace(r#""focused":true,"#, "").replace(r#""name":"Firefox","#, r#""name":"Firefox","focused":true,"#);
+        // window event
+        event_send.send(3).unwrap();
+        assert_eq!(recv.recv().await.unwrap().active_window, ActiveWindow { title: "Firefox".into(), class: "firefox".into() });
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
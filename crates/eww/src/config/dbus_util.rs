@@ -0,0 +1,78 @@
+//! Helpers for reading the loosely typed values that D-Bus services send, as used by the D-Bus based magic variables.
+
+use zbus::zvariant::Value;
+
+/// Values in `a{sv}` dictionaries may be wrapped in another variant
+pub fn unwrap_variant<'a>(value: &'a Value<'a>) -> &'a Value<'a> {
+    match value {
+        Value::Value(inner) => unwrap_variant(inner),
+        value => value,
+    }
+}
+
+pub fn as_str<'a>(value: &'a Value<'a>) -> Option<&'a str> {
+    match unwrap_variant(value) {
+        Value::Str(x) => Some(x.as_str()),
+        Value::ObjectPath(x) => Some(x.as_str()),
+        _ => None,
+    }
+}
+
+pub fn as_bool(value: &Value) -> Option<bool> {
+    match unwrap_variant(value) {
+        Value::Bool(x) => Some(*x),
+        _ => None,
+    }
+}
+
+/// Services don't always agree on the integer type of a value, so accept all of them
+pub fn as_i64(value: &Value) -> Option<i64> {
+    match unwrap_variant(value) {
+        Value::U8(x) => Some(*x as i64),
+        Value::I16(x) => Some(*x as i64),
+        Value::U16(x) => Some(*x as i64),
+        Value::I32(x) => Some(*x as i64),
+        Value::U32(x) => Some(*x as i64),
+        Value::I64(x) => Some(*x),
+        Value::U64(x) => Some(*x as i64),
+        Value::F64(x) => Some(*x as i64),
+        _ => None,
+    }
+}
+
+pub fn as_f64(value: &Value) -> Option<f64> {
+    match unwrap_variant(value) {
+        Value::F64(x) => Some(*x),
+        value => as_i64(value).map(|x| x as f64),
+    }
+}
+
+/// Convert any D-Bus value to JSON. Dictionary keys that aren't strings are converted to their string representation.
+pub fn to_json(value: &Value) -> serde_json::Value {
+    use serde_json::Value as Json;
+    match unwrap_variant(value) {
+        Value::Bool(x) => Json::from(*x),
+        Value::F64(x) => Json::from(*x),
+        Value::U64(x) => Json::from(*x),
+        Value::Str(x) => Json::from(x.as_str()),
+        Value::ObjectPath(x) => Json::from(x.as_str()),
+        Value::Signature(x) => Json::from(x.to_string()),
+        Value::Array(array) => Json::Array(array.inner().iter().map(to_json).collect()),
+        Value::Dict(dict) => Json::Object(
+            dict.iter()
+                .map(|(key, value)| {
+                    let key = match to_json(key) {
+                        Json::String(key) => key,
+                        key => key.to_string(),
+                    };
+                    (key, to_json(value))
+                })
+                .collect(),
+        ),
+        Value::Structure(structure) => Json::Array(structure.fields().iter().map(to_json).collect()),
+        value => match as_i64(value) {
+            Some(x) => Json::from(x),
+            None => Json::from(value.to_string()),
+        },
+    }
+}
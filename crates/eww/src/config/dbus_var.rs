@@ -0,0 +1,265 @@
+//! Variables bound to D-Bus properties or signals (`defdbus`), and calling D-Bus methods for `eww call-dbus`.
+
+use anyhow::{Context, Result, bail};
+use futures::StreamExt;
+use simplexpr::dynval::DynVal;
+use yuck::config::script_var_definition::{DbusBus, DbusVar, DbusVarSource};
+use zbus::{
+    MatchRule, MessageStream,
+    fdo::PropertiesProxy,
+    message::Body,
+    names::InterfaceName,
+    zvariant::{ObjectPath, Structure, StructureBuilder, Value},
+};
+
+use super::dbus_util::to_json;
+
+pub async fn connect(bus: DbusBus) -> zbus::Result<zbus::Connection> {
+    match bus {
+        DbusBus::Session => zbus::Connection::session().await,
+        DbusBus::System => zbus::Connection::system().await,
+    }
+}
+
+/// Keep sending the value of the given variable until the connection is closed.
+pub async fn run(var: &DbusVar, send: impl Fn(DynVal)) -> Result<()> {
+    let con = connect(var.bus).await.with_context(|| format!("Failed to connect to the {:?} bus", var.bus))?;
+    run_on(&con, var, send).await
+}
+
+async fn run_on(con: &zbus::Connection, var: &DbusVar, send: impl Fn(DynVal)) -> Result<()> {
+    match &var.source {
+        DbusVarSource::Property(property) => watch_property(con, var, property, send).await,
+        DbusVarSource::Signal(signal) => watch_signal(con, var, signal, send).await,
+    }
+}
+
+async fn watch_property(con: &zbus::Connection, var: &DbusVar, property: &str, send: impl Fn(DynVal)) -> Result<()> {
+    let destination = var.destination.clone().context("D-Bus property variables need a destination")?;
+    let interface = InterfaceName::try_from(var.interface.as_str())?;
+    let properties = PropertiesProxy::builder(con).destination(destination)?.path(var.path.clone())?.build().await?;
+    // subscribe to changes before reading the initial value, to not miss a change in between
+    let mut changes = properties.receive_properties_changed().await?;
+    let get = async || -> Result<DynVal> {
+        let value = properties.get(interface.clone(), property).await?;
+        Ok(to_dynval(&value))
+    };
+
+    send(get().await?);
+    while let Some(change) = changes.next().await {
+        let args = change.args()?;
+        if args.interface_name() != &interface {
+            continue;
+        }
+        if let Some(value) = args.changed_properties().get(property) {
+            send(to_dynval(value));
+        } else if args.invalidated_properties().contains(&property) {
+            send(get().await?);
+        }
+    }
+    Ok(())
+}
+
+async fn watch_signal(con: &zbus::Connection, var: &DbusVar, signal: &str, send: impl Fn(DynVal)) -> Result<()> {
+    let mut rule = MatchRule::builder()
+        .msg_type(zbus::message::Type::Signal)
+        .path(var.path.as_str())?
+        .interface(var.interface.as_str())?
+        .member(signal)?;
+    if let Some(destination) = &var.destination {
+        rule = rule.sender(destination.as_str())?;
+    }
+    let mut signals = MessageStream::for_match_rule(rule.build(), con, None).await?;
+    while let Some(msg) = signals.next().await {
+        send(DynVal::from(body_to_json(&msg?.body())?.to_string()));
+    }
+    Ok(())
+}
+
+/// Strings are used as is, everything else is converted to JSON.
+fn to_dynval(value: &Value) -> DynVal {
+    match to_json(value) {
+        serde_json::Value::String(x) => DynVal::from(x),
+        value => DynVal::from(value.to_string()),
+    }
+}
+
+/// Convert the arguments of a message to a JSON array.
+fn body_to_json(body: &Body) -> Result<serde_json::Value> {
+    if body.is_empty() {
+        return Ok(serde_json::Value::Array(Vec::new()));
+    }
+    let args: Structure = body.deserialize()?;
+    Ok(serde_json::Value::Array(args.fields().iter().map(to_json).collect()))
+}
+
+/// Call a method and return its reply as JSON. A reply with a single value is returned as that value,
+/// longer ones as an array.
+///
+/// The types of the `args` are given by `signature`, which may only contain basic types.
+/// Without one, they are guessed: `true` and `false` are booleans, numbers are `i` or `d`, anything else is a string.
+pub async fn call_method(
+    bus: DbusBus,
+    destination: &str,
+    path: &str,
+    interface: &str,
+    method: &str,
+    signature: Option<&str>,
+    args: &[String],
+) -> Result<String> {
+    let con = connect(bus).await.with_context(|| format!("Failed to connect to the {:?} bus", bus))?;
+    call_method_on(&con, destination, path, interface, method, signature, args).await
+}
+
+async fn call_method_on(
+    con: &zbus::Connection,
+    destination: &str,
+    path: &str,
+    interface: &str,
+    method: &str,
+    signature: Option<&str>,
+    args: &[String],
+) -> Result<String> {
+    let args = match signature {
+        Some(signature) if signature.chars().count() != args.len() => {
+            bail!("The signature `{}` does not match the {} given arguments", signature, args.len())
+        }
+        Some(signature) => signature.chars().zip(args).map(|(kind, arg)| parse_arg(kind, arg)).collect::<Result<Vec<_>>>()?,
+        None => args.iter().map(|arg| guess_arg(arg)).collect(),
+    };
+
+    let reply = if args.is_empty() {
+        con.call_method(Some(destination), path, Some(interface), method, &()).await?
+    } else {
+        let body = args.into_iter().fold(StructureBuilder::new(), StructureBuilder::append_field).build()?;
+        con.call_method(Some(destination), path, Some(interface), method, &body).await?
+    };
+    Ok(match body_to_json(&reply.body())? {
+        serde_json::Value::Array(mut values) if values.len() == 1 => values.remove(0).to_string(),
+        values => values.to_string(),
+    })
+}
+
+fn parse_arg(kind: char, arg: &str) -> Result<Value<'static>> {
+    let invalid = || format!("`{}` is not a valid value of type `{}`", arg, kind);
+    Ok(match kind {
+        's' => Value::from(arg.to_string()),
+        'o' => Value::from(ObjectPath::try_from(arg.to_string()).with_context(invalid)?),
+        'b' => Value::from(arg.parse::<bool>().with_context(invalid)?),
+        'y' => Value::from(arg.parse::<u8>().with_context(invalid)?),
+        'n' => Value::from(arg.parse::<i16>().with_context(invalid)?),
+        'q' => Value::from(arg.parse::<u16>().with_context(invalid)?),
+        'i' => Value::from(arg.parse::<i32>().with_context(invalid)?),
+        'u' => Value::from(arg.parse::<u32>().with_context(invalid)?),
+        'x' => Value::from(arg.parse::<i64>().with_context(invalid)?),
+        't' => Value::from(arg.parse::<u64>().with_context(invalid)?),
+        'd' => Value::from(arg.parse::<f64>().with_context(invalid)?),
+        'v' => Value::Value(Box::new(guess_arg(arg))),
+        _ => bail!("Unsupported type `{}` in signature, only basic types can be passed as arguments", kind),
+    })
+}
+
+fn guess_arg(arg: &str) -> Value<'static> {
+    if let Ok(x) = arg.parse::<bool>() {
+        Value::from(x)
+    } else if let Ok(x) = arg.parse::<i32>() {
+        Value::from(x)
+    } else if let Ok(x) = arg.parse::<f64>() {
+        Value::from(x)
+    } else {
+        Value::from(arg.to_string())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::private_bus::PrivateBus;
+    use eww_shared_util::{Span, VarName};
+    use zbus::{interface, object_server::SignalEmitter};
+
+    struct TestService {
+        level: u32,
+    }
+
+    #[interface(name = "org.eww.Test")]
+    impl TestService {
+        #[zbus(property)]
+        fn level(&self) -> u32 {
+            self.level
+        }
+
+        fn add(&mut self, amount: u32, reason: &str) -> (u32, String) {
+            self.level += amount;
+            (self.level, reason.to_string())
+        }
+
+        #[zbus(signal)]
+        async fn changed(ctxt: &SignalEmitter<'_>, name: &str, value: i32) -> zbus::Result<()>;
+    }
+
+    fn var(source: DbusVarSource) -> DbusVar {
+        DbusVar {
+            name: VarName::from("foo"),
+            bus: DbusBus::Session,
+            destination: Some("org.eww.Test".to_string()),
+            path: "/org/eww/Test".to_string(),
+            interface: "org.eww.Test".to_string(),
+            source,
+            initial_value: DynVal::from(""),
+            name_span: Span::DUMMY,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dbus_var() {
+        let Some(bus) = PrivateBus::start() else {
+            return;
+        };
+        let service_con = bus.connection().await;
+        service_con.object_server().at("/org/eww/Test", TestService { level: 1 }).await.unwrap();
+        service_con.request_name("org.eww.Test").await.unwrap();
+
+        let (send, mut property_recv) = tokio::sync::mpsc::unbounded_channel();
+        let eww_con = bus.connection().await;
+        tokio::spawn(async move {
+            run_on(&eww_con, &var(DbusVarSource::Property("Level".to_string())), |value| send.send(value).unwrap()).await
+        });
+        let (send, mut signal_recv) = tokio::sync::mpsc::unbounded_channel();
+        let eww_con = bus.connection().await;
+        tokio::spawn(async move {
+            run_on(&eww_con, &var(DbusVarSource::Signal("Changed".to_string())), |value| send.send(value).unwrap()).await
+        });
+        assert_eq!(property_recv.recv().await.unwrap(), DynVal::from("1"));
+
+        let reply = call_method_on(
+            &bus.connection().await,
+            "org.eww.Test",
+            "/org/eww/Test",
+            "org.eww.Test",
+            "Add",
+            Some("us"),
+            &["2".to_string(), "test".to_string()],
+        )
+        .await
+        .unwrap();
+        assert_eq!(reply, r#"[3,"test"]"#);
+        let service = service_con.object_server().interface::<_, TestService>("/org/eww/Test").await.unwrap();
+        service.get().await.level_changed(service.signal_emitter()).await.unwrap();
+        assert_eq!(property_recv.recv().await.unwrap(), DynVal::from("3"));
+
+        TestService::changed(service.signal_emitter(), "x", -1).await.unwrap();
+        assert_eq!(signal_recv.recv().await.unwrap(), DynVal::from(r#"["x",-1]"#));
+    }
+
+    #[test]
+    fn test_parse_args() {
+        assert_eq!(guess_arg("true"), Value::from(true));
+        assert_eq!(guess_arg("12"), Value::from(12_i32));
+        assert_eq!(guess_arg("1.5"), Value::from(1.5));
+        assert_eq!(guess_arg("foo"), Value::from("foo"));
+        assert_eq!(parse_arg('t', "12").unwrap(), Value::from(12_u64));
+        assert!(parse_arg('u', "-1").is_err());
+        assert!(parse_arg('a', "[]").is_err());
+    }
+}
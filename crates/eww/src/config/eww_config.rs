@@ -3,7 +3,11 @@ use eww_shared_util::VarName;
 use std::collections::HashMap;
 use yuck::{
     config::{
-        Config, script_var_definition::ScriptVarDefinition, validate::ValidationError, widget_definition::WidgetDefinition,
+        Config,
+        notification_server_definition::NotificationServerDefinition,
+        script_var_definition::{ListenVarSource, ScriptVarDefinition},
+        validate::ValidationError,
+        widget_definition::WidgetDefinition,
         window_definition::WindowDefinition,
     },
     error::DiagError,
@@ -14,7 +18,7 @@ use simplexpr::dynval::DynVal;
 
 use crate::{config::inbuilt, error_handling_ctx, file_database::FileDatabase, paths::EwwPaths, widgets::widget_definitions};
 
-use super::script_var;
+use super::script_var::{self, ListenVarStatus};
 
 /// Load an [`EwwConfig`] from the config dir of the given [`crate::EwwPaths`],
 /// resetting and applying the global YuckFiles object in [`crate::error_handling_ctx`].
@@ -23,6 +27,20 @@ pub fn read_from_eww_paths(eww_paths: &EwwPaths) -> Result<EwwConfig> {
     EwwConfig::read_from_dir(&mut error_handling_ctx::FILE_DATABASE.write().unwrap(), eww_paths)
 }
 
+/// Variables that are published alongside user-defined script vars, such as the error of a polling variable,
+/// together with their initial values.
+fn companion_vars<'a>(script_vars: impl Iterator<Item = &'a ScriptVarDefinition>) -> Vec<(VarName, DynVal)> {
+    script_vars
+        .filter_map(|var| match var {
+            ScriptVarDefinition::Poll(var) => Some((var.error_var_name()?, DynVal::from(""))),
+            ScriptVarDefinition::Listen(var) if matches!(var.command, ListenVarSource::Shell(..)) => {
+                Some((var.status_var_name(), ListenVarStatus::default().to_dynval()))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
 /// Eww configuration structure.
 #[derive(Debug, Clone, Default)]
 pub struct EwwConfig {
@@ -30,6 +48,7 @@ pub struct EwwConfig {
     windows: HashMap<String, WindowDefinition>,
     initial_variables: HashMap<VarName, DynVal>,
     script_vars: HashMap<VarName, ScriptVarDefinition>,
+    notification_server: Option<NotificationServerDefinition>,
 
     // map of variables to all pollvars which refer to them in their run-while-expression
     run_while_mentions: HashMap<VarName, Vec<VarName>>,
@@ -44,9 +63,18 @@ impl EwwConfig {
         }
         let config = Config::generate_from_main_file(files, yuck_path)?;
 
+        let inbuilt_vars = inbuilt::get_inbuilt_vars();
+        let companion_vars = companion_vars(config.script_vars.values());
+
         // run some validations on the configuration
-        let magic_globals: Vec<_> =
-            inbuilt::INBUILT_VAR_NAMES.iter().chain(inbuilt::MAGIC_CONSTANT_NAMES).map(|x| VarName::from(*x)).collect();
+        let magic_globals: Vec<_> = inbuilt::INBUILT_VAR_NAMES
+            .iter()
+            .chain(inbuilt::INBUILT_LISTEN_VAR_NAMES)
+            .chain(&[inbuilt::NOTIFICATIONS_VAR_NAME])
+            .chain(inbuilt::MAGIC_CONSTANT_NAMES)
+            .map(|x| VarName::from(*x))
+            .chain(companion_vars.iter().map(|(name, _)| name.clone()))
+            .collect();
         yuck::config::validate::validate(&config, magic_globals)?;
 
         for (name, def) in &config.widget_definitions {
@@ -57,8 +85,35 @@ impl EwwConfig {
             }
         }
 
-        let Config { widget_definitions, window_definitions, mut var_definitions, mut script_vars } = config;
-        script_vars.extend(inbuilt::get_inbuilt_vars());
+        let Config {
+            widget_definitions,
+            window_definitions,
+            mut var_definitions,
+            mut script_vars,
+            builtin_var_overrides,
+            notification_server,
+        } = config;
+        script_vars.extend(inbuilt_vars);
+        script_vars.extend(inbuilt::get_inbuilt_listen_vars());
+        for (name, var_override) in builtin_var_overrides {
+            let Some(ScriptVarDefinition::Poll(var)) =
+                script_vars.get_mut(&name).filter(|_| inbuilt::INBUILT_VAR_NAMES.contains(&name.0.as_str()))
+            else {
+                return Err(DiagError(ValidationError::UnknownBuiltinVar(var_override.name_span, name).to_diagnostic()).into());
+            };
+            if let Some(interval) = var_override.interval {
+                var.interval = interval;
+            }
+            if let Some(run_while_expr) = var_override.run_while_expr {
+                var.run_while_expr = run_while_expr;
+            }
+            if var_override.legacy_keys {
+                let Some(command) = inbuilt::get_legacy_var_source(&name) else {
+                    return Err(DiagError(ValidationError::NoLegacyKeys(var_override.name_span, name).to_diagnostic()).into());
+                };
+                var.command = command;
+            }
+        }
         var_definitions.extend(inbuilt::get_magic_constants(eww_paths));
 
         let mut run_while_mentions = HashMap::<VarName, Vec<VarName>>::new();
@@ -70,11 +125,16 @@ impl EwwConfig {
             }
         }
 
+        let mut initial_variables: HashMap<_, _> = var_definitions.into_iter().map(|(k, v)| (k, v.initial_value)).collect();
+        initial_variables.insert(VarName::from(inbuilt::NOTIFICATIONS_VAR_NAME), DynVal::from("[]"));
+        initial_variables.extend(companion_vars);
+
         Ok(EwwConfig {
             windows: window_definitions,
             widgets: widget_definitions,
-            initial_variables: var_definitions.into_iter().map(|(k, v)| (k, v.initial_value)).collect(),
+            initial_variables,
             script_vars,
+            notification_server,
             run_while_mentions,
         })
     }
@@ -112,6 +172,10 @@ impl EwwConfig {
         &self.widgets
     }
 
+    pub fn get_notification_server(&self) -> Option<&NotificationServerDefinition> {
+        self.notification_server.as_ref()
+    }
+
     /// Given a variable name, get the names of all variables that reference that variable in their run-while (active/inactive) state
     pub fn get_run_while_mentions_of(&self, name: &VarName) -> Option<&Vec<VarName>> {
         self.run_while_mentions.get(name)
@@ -2,11 +2,17 @@ use std::collections::HashMap;
 
 use simplexpr::{SimplExpr, dynval::DynVal};
 use yuck::config::{
-    script_var_definition::{PollScriptVar, ScriptVarDefinition, VarSource},
+    script_var_definition::{
+        ListenScriptVar, ListenVarMode, ListenVarSource, PollErrorBehavior, PollScriptVar, RestartPolicy, ScriptVarDefinition,
+        VarSource,
+    },
     var_definition::VarDefinition,
 };
 
-use crate::{config::system_stats::*, paths::EwwPaths};
+use crate::{
+    config::{compositor, mpris, system_stats::*, upower},
+    paths::EwwPaths,
+};
 use eww_shared_util::VarName;
 
 macro_rules! define_builtin_vars {
@@ -21,6 +27,10 @@ macro_rules! define_builtin_vars {
                     command: VarSource::Function($fun),
                     initial_value: None,
                     interval: std::time::Duration::from_secs($interval),
+                    timeout: None,
+                    on_error: PollErrorBehavior::Keep,
+                    error_value: None,
+                    allowed_paths: Vec::new(),
                     name_span: eww_shared_util::span::Span::DUMMY,
                 })
                 ),*
@@ -30,20 +40,24 @@ macro_rules! define_builtin_vars {
 }
 
 define_builtin_vars! {
-    // @desc EWW_TEMPS - Heat of the components in degree Celsius
-    // @prop { <name>: temperature }
-    "EWW_TEMPS" [2] => || Ok(DynVal::from(get_temperatures())),
+    // @desc EWW_TEMPS - Heat of the components in degree Celsius, grouped by their hwmon chip, with their maximum and critical thresholds. Chips sharing a name with another one, like a second nvme drive, are keyed by their name and hwmon device, i.e. `nvme_hwmon3`, and sensors sharing a label with another one on the same chip by their label and index, i.e. `AUXTIN_temp4`. Use `(defbuiltin EWW_TEMPS :legacy-keys true)` to get the old flat `<LABEL>: "<temperature>"` format instead. Example: `{EWW_TEMPS.coretemp["Package id 0"].temp}`
+    // @prop { <chip>: { <sensor>: { temp, max, critical } } }
+    "EWW_TEMPS" [2] => || Ok(DynVal::from(get_temperatures(false))),
+
+    // @desc EWW_FANS - Speed of the fans in RPM and their pwm duty cycle (0-255 and in percent), grouped by their hwmon chip. Example: `{EWW_FANS.thinkpad.fan1.rpm}`
+    // @prop { <chip>: { <fan>: { rpm, min, max, pwm, pwm_perc } } }
+    "EWW_FANS" [2] => || Ok(DynVal::from(get_fans())),
 
     // @desc EWW_RAM - Information on ram and swap usage in bytes.
     // @prop { total_mem, free_mem, total_swap, free_swap, available_mem, used_mem, used_mem_perc }
     "EWW_RAM" [2] => || Ok(DynVal::from(get_ram())),
 
-    // @desc EWW_DISK - Information on on all mounted partitions (Might report inaccurately on some filesystems, like btrfs and zfs) Example: `{EWW_DISK["/"]}`
-    // @prop { <mount_point>: { name, total, free, used, used_perc } }
+    // @desc EWW_DISK - Information on on all mounted partitions (Might report inaccurately on some filesystems, like btrfs and zfs), as well as the I/O of all block devices in bytes and operations per second. Example: `{EWW_DISK["/"]}`, `{EWW_DISK.nvme0n1.write_rate}`
+    // @prop { <mount_point>: { name, total, free, used, used_perc, read_rate, write_rate, read_iops, write_iops }, <device>: { read_rate, write_rate, read_iops, write_iops } }
     "EWW_DISK" [2] => || Ok(DynVal::from(get_disks())),
 
-    // @desc EWW_BATTERY - Battery capacity in percent of the main battery
-    // @prop { <name>: { capacity, status } }
+    // @desc EWW_BATTERY - Information on all batteries: capacity in percent, energy in Wh, power draw in W, remaining time in seconds and health. `total` combines all batteries into one, `ac_online` tells whether the system is plugged in
+    // @prop { <name>: { capacity, status, energy_now, energy_full, energy_full_design, power_now, time_to_empty, time_to_full, health_perc, cycle_count }, total: { <same as above> }, total_avg, ac_online }
     "EWW_BATTERY" [2] => || Ok(DynVal::from(
         match get_battery_capacity() {
             Err(e) => {
@@ -58,18 +72,80 @@ define_builtin_vars! {
     // @prop { cores: [{ core, freq, usage }], avg }
     "EWW_CPU" [2] => || Ok(DynVal::from(get_cpus())) ,
 
-    // @desc EWW_GPU - Information on the GPU (chip and vram) usage and frequency
-    // @prop { gpu_load, vram_current, vrma_max, freq_graphics_current, freq_graphics_max, freq_vram_current, freq_vram_max }
-    "EWW_GPU" [2] => || Ok(DynVal::from(get_gpus())) ,
+    // @desc EWW_GPU - Information on all GPUs (chip and vram) usage, frequency, temperature and power draw. Supports nvidia (via NVML, requires the `nvidia` feature), amdgpu and intel (i915/xe) cards. Use `(defbuiltin EWW_GPU :legacy-keys true)` to get the old flat `NVIDIA_GPU_<KEY>_<index>` format instead, which uses the same keys for the gpus of all vendors. Example: `{EWW_GPU[0].load}`
+    // @prop [{ vendor, name, load, vram_used, vram_total, freq_graphics_current, freq_graphics_max, freq_memory_current, freq_memory_max, temp, power_w }]
+    "EWW_GPU" [2] => || Ok(DynVal::from(get_gpus(false))) ,
 
-    // @desc EWW_NET - Bytes up/down on all interfaces
-    // @prop { <name>: { up, down } }
+    // @desc EWW_NET - Information on all network interfaces: transfer rates in bytes per second, total bytes transferred, addresses and link state. Example: `{EWW_NET.wlan0.down_rate}`
+    // @prop { <name>: { up_rate, down_rate, total_up, total_down, ipv4, ipv6, mac, mtu, operstate, wireless, NET_UP, NET_DOWN } }
     "EWW_NET" [2] => || Ok(DynVal::from(net())) ,
 
+    // @desc EWW_PROCS - The processes using the most CPU and memory, as well as the number of total and running processes. `cpu` is in percent of a single core, `rss` in bytes
+    // @prop { top_cpu: [{ pid, name, cmd, cpu, rss, user }], top_mem: [{ pid, name, cmd, cpu, rss, user }], total, running }
+    "EWW_PROCS" [2] => || Ok(DynVal::from(get_processes())),
+
+    // @desc EWW_SYS - General information on the system. `uptime` is in seconds, `boot_time` is a UNIX timestamp, `cpus` is the number of logical cores
+    // @prop { load_avg_1, load_avg_5, load_avg_15, uptime, boot_time, hostname, kernel, os, os_version, user, cpus, physical_cores }
+    "EWW_SYS" [2] => || Ok(DynVal::from(get_sys_info())),
+
     // @desc EWW_TIME - the current UNIX timestamp
     "EWW_TIME" [1] => || Ok(DynVal::from(get_time())) ,
 }
 
+/// Get the source of a builtin variable that produces the format it had in older versions of eww,
+/// as enabled by `(defbuiltin NAME :legacy-keys true)`.
+pub fn get_legacy_var_source(name: &VarName) -> Option<VarSource> {
+    match name.0.as_str() {
+        "EWW_GPU" => Some(VarSource::Function(|| Ok(DynVal::from(get_gpus(true))))),
+        "EWW_TEMPS" => Some(VarSource::Function(|| Ok(DynVal::from(get_temperatures(true))))),
+        _ => None,
+    }
+}
+
+macro_rules! define_builtin_listen_vars {
+    ($($name:literal [$initial:literal] => $fun:expr),*$(,)?) => {
+        pub static INBUILT_LISTEN_VAR_NAMES: &[&'static str] = &[$($name),*];
+        pub fn get_inbuilt_listen_vars() -> HashMap<VarName, ScriptVarDefinition> {
+            maplit::hashmap! {
+                $(
+                VarName::from($name) => ScriptVarDefinition::Listen(ListenScriptVar {
+                    name: VarName::from($name),
+                    command: ListenVarSource::Function($fun),
+                    initial_value: DynVal::from($initial),
+                    mode: ListenVarMode::Replace,
+                    restart: RestartPolicy::Never,
+                    restart_delay: ListenScriptVar::DEFAULT_RESTART_DELAY,
+                    max_retries: None,
+                    name_span: eww_shared_util::span::Span::DUMMY,
+                })
+                ),*
+            }
+        }
+    }
+}
+
+define_builtin_listen_vars! {
+    // @desc EWW_MEDIA - Information on all media players supporting MPRIS, updated whenever a player changes. `active` is the name of the player that most recently started playing. `position` and `length` are in seconds, `status` is one of `Playing`, `Paused` or `Stopped` and `loop` one of `None`, `Track` or `Playlist`. Example: `{EWW_MEDIA.players[EWW_MEDIA.active].title}`
+    // @prop { players: { <name>: { title, artist, album, art_url, position, length, status, shuffle, loop } }, active }
+    "EWW_MEDIA" [r#"{"players":{},"active":null}"#] => |send| Box::pin(mpris::listen(send)),
+
+    // @desc EWW_UPOWER - Information on all power devices known to UPower, including peripherals such as mice or headsets, as well as the active profile of power-profiles-daemon. Devices are keyed by the last segment of their UPower object path, i.e. `battery_BAT0`. `state` is one of `charging`, `discharging`, `empty`, `fully-charged`, `pending-charge`, `pending-discharge` or `unknown`, times are in seconds (`0` if unknown) and `energy_rate` is in W. Use `eww set-power-profile` to change the power profile. Example: `{EWW_UPOWER.devices.battery_BAT0.percentage}`
+    // @prop { devices: { <name>: { type, vendor, model, native_path, percentage, state, time_to_empty, time_to_full, energy_rate, power_supply, online, is_present, icon_name } }, power_profile, power_profiles }
+    "EWW_UPOWER" [r#"{"devices":{},"power_profile":null,"power_profiles":[]}"#] => |send| Box::pin(upower::listen(send)),
+
+    // @desc EWW_WORKSPACES - The workspaces of Hyprland or sway, read from their IPC sockets and updated on every change. `windows` is the amount of windows on the workspace. Example: `{EWW_WORKSPACES[0].focused}`
+    // @prop [{ id, name, monitor, windows, focused, urgent }]
+    "EWW_WORKSPACES" ["[]"] => |send| Box::pin(compositor::listen_workspaces(send)),
+
+    // @desc EWW_ACTIVE_WINDOW - The title and class of the focused window in Hyprland or sway. For native wayland windows on sway, `class` is the app id. Both are empty if no window is focused.
+    // @prop { title, class }
+    "EWW_ACTIVE_WINDOW" [r#"{"title":"","class":""}"#] => |send| Box::pin(compositor::listen_active_window(send)),
+}
+
+// @desc EWW_NOTIFICATIONS - The notifications received by eww's notification server, newest first. The server is only started if `(defnotifications :history 50)` is part of your configuration, with `:history` being the amount of notifications to keep. Use `eww dismiss-notifications` and `eww invoke-notification-action` to act on them. `urgency` is one of `low`, `normal` or `critical`, `expire_timeout` is in milliseconds and `timestamp` is a UNIX timestamp. Example: `{EWW_NOTIFICATIONS[0].summary}`
+// @prop [{ id, app_name, app_icon, summary, body, actions: [{ key, label }], urgency, expire_timeout, timestamp, expired }]
+pub static NOTIFICATIONS_VAR_NAME: &str = "EWW_NOTIFICATIONS";
+
 macro_rules! define_magic_constants {
     ($eww_paths:ident, $($name:literal => $value:expr),*$(,)?) => {
         pub static MAGIC_CONSTANT_NAMES: &[&'static str] = &[$($name),*];
@@ -1,7 +1,14 @@
+pub mod compositor;
+pub mod dbus_util;
+pub mod dbus_var;
 pub mod eww_config;
 pub mod inbuilt;
+pub mod mpris;
+#[cfg(test)]
+pub mod private_bus;
 pub mod script_var;
 pub mod scss;
 pub mod system_stats;
+pub mod upower;
 pub use eww_config::*;
 pub use script_var::*;
@@ -0,0 +1,292 @@
+//! Tracking of media players through the [MPRIS](https://specifications.freedesktop.org/mpris-spec/latest/) D-Bus interface,
+//! used for the `EWW_MEDIA` magic variable.
+
+use std::collections::{BTreeMap, HashMap};
+
+use futures::StreamExt;
+use simplexpr::dynval::DynVal;
+use yuck::config::script_var_definition::ListenVarSender;
+
+use super::dbus_util::{as_bool, as_i64, as_str, unwrap_variant};
+use zbus::{
+    MatchRule, MessageStream,
+    fdo::{DBusProxy, PropertiesProxy},
+    names::{BusName, InterfaceName, OwnedUniqueName},
+    zvariant::{OwnedValue, Value},
+};
+
+const MPRIS_PREFIX: &str = "org.mpris.MediaPlayer2.";
+const MPRIS_PATH: &str = "/org/mpris/MediaPlayer2";
+const PLAYER_INTERFACE: &str = "org.mpris.MediaPlayer2.Player";
+
+#[derive(Debug, Default, Clone, PartialEq, serde::Serialize)]
+struct PlayerData {
+    title: Option<String>,
+    /// All artists of the track, joined with `, `
+    artist: Option<String>,
+    album: Option<String>,
+    art_url: Option<String>,
+    /// Position and length in seconds
+    position: Option<f64>,
+    length: Option<f64>,
+    /// One of `Playing`, `Paused` or `Stopped`
+    status: String,
+    shuffle: Option<bool>,
+    /// One of `None`, `Track` or `Playlist`
+    #[serde(rename = "loop")]
+    loop_status: Option<String>,
+}
+
+impl PlayerData {
+    /// Apply the given properties of the `org.mpris.MediaPlayer2.Player` interface, as returned by `GetAll`
+    /// or sent in `PropertiesChanged`. Properties that aren't contained are left unchanged.
+    fn update(&mut self, properties: &HashMap<String, OwnedValue>) {
+        for (property, value) in properties {
+            match property.as_str() {
+                "PlaybackStatus" => self.status = as_str(value).unwrap_or_default().to_string(),
+                "LoopStatus" => self.loop_status = as_str(value).map(str::to_string),
+                "Shuffle" => self.shuffle = as_bool(value),
+                "Position" => self.position = as_i64(value).map(micros_to_secs),
+                "Metadata" => self.update_metadata(value),
+                _ => {}
+            }
+        }
+    }
+
+    fn update_metadata(&mut self, metadata: &Value) {
+        let Value::Dict(metadata) = unwrap_variant(metadata) else {
+            return;
+        };
+        let fields: HashMap<&str, &Value> =
+            metadata.iter().filter_map(|(key, value)| Some((as_str(key)?, unwrap_variant(value)))).collect();
+        let string_field = |name: &str| fields.get(name).and_then(|value| as_str(value)).map(str::to_string);
+
+        self.title = string_field("xesam:title");
+        self.album = string_field("xesam:album");
+        self.art_url = string_field("mpris:artUrl");
+        self.length = fields.get("mpris:length").and_then(|value| as_i64(value)).map(micros_to_secs);
+        self.artist = match fields.get("xesam:artist") {
+            Some(Value::Array(artists)) => Some(artists.inner().iter().filter_map(as_str).collect::<Vec<_>>().join(", ")),
+            Some(artist) => as_str(artist).map(str::to_string),
+            None => None,
+        };
+    }
+}
+
+#[derive(Debug, Default)]
+struct Players {
+    /// Players, keyed by their bus name without the `org.mpris.MediaPlayer2.` prefix
+    players: BTreeMap<String, PlayerData>,
+    /// Signals are sent from the unique name of a player, so we need to know which player that refers to
+    owners: HashMap<OwnedUniqueName, String>,
+    active: Option<String>,
+}
+
+impl Players {
+    fn add(&mut self, name: String, owner: OwnedUniqueName, data: PlayerData) {
+        self.owners.insert(owner, name.clone());
+        self.players.insert(name.clone(), data);
+        self.update_active(&name);
+    }
+
+    fn remove(&mut self, name: &str) {
+        self.owners.retain(|_, player| player != name);
+        self.players.remove(name);
+        if self.active.as_deref() == Some(name) {
+            self.active = None;
+            self.update_active(name);
+        }
+    }
+
+    fn update(&mut self, owner: &OwnedUniqueName, update: impl FnOnce(&mut PlayerData)) {
+        let Some(name) = self.owners.get(owner).cloned() else {
+            return;
+        };
+        if let Some(player) = self.players.get_mut(&name) {
+            update(player);
+            self.update_active(&name);
+        }
+    }
+
+    /// The active player is the one that most recently started playing.
+    /// If it stops existing, we fall back to any other playing player, or otherwise any player at all.
+    fn update_active(&mut self, changed: &str) {
+        let is_playing = |name: &str| self.players.get(name).is_some_and(|player| player.status == "Playing");
+        if is_playing(changed) {
+            self.active = Some(changed.to_string());
+        } else if self.active.is_none() || self.active.as_deref().is_some_and(|active| !self.players.contains_key(active)) {
+            self.active = self.players.keys().find(|name| is_playing(name)).or_else(|| self.players.keys().next()).cloned();
+        }
+    }
+
+    fn to_dynval(&self) -> DynVal {
+        DynVal::from(serde_json::json!({ "players": self.players, "active": self.active }).to_string())
+    }
+}
+
+pub async fn listen(send: ListenVarSender) -> Result<(), Box<dyn std::error::Error + Sync + Send + 'static>> {
+    let con = zbus::Connection::session().await?;
+    Ok(run(&con, send).await?)
+}
+
+async fn run(con: &zbus::Connection, send: ListenVarSender) -> zbus::Result<()> {
+    let dbus = DBusProxy::new(con).await?;
+    // subscribe to changes before reading the initial state, to not miss any players appearing in between
+    let mut owner_changes = dbus.receive_name_owner_changed().await?;
+    let rule = MatchRule::builder().msg_type(zbus::message::Type::Signal).path(MPRIS_PATH)?.build();
+    let mut signals = MessageStream::for_match_rule(rule, con, None).await?;
+
+    let mut players = Players::default();
+    for name in dbus.list_names().await? {
+        if name.starts_with(MPRIS_PREFIX) {
+            add_player(con, &dbus, &mut players, name.into_inner()).await;
+        }
+    }
+    send(players.to_dynval());
+
+    loop {
+        tokio::select! {
+            Some(change) = owner_changes.next() => {
+                let args = change.args()?;
+                if !args.name().starts_with(MPRIS_PREFIX) {
+                    continue;
+                }
+                players.remove(&args.name()[MPRIS_PREFIX.len()..]);
+                if args.new_owner().is_some() {
+                    add_player(con, &dbus, &mut players, args.name().to_owned()).await;
+                }
+            }
+            Some(msg) = signals.next() => {
+                let msg = msg?;
+                let header = msg.header();
+                let Some(sender) = header.sender().map(|sender| OwnedUniqueName::from(sender.to_owned())) else {
+                    continue;
+                };
+                match header.member().map(|member| member.as_str()) {
+                    Some("PropertiesChanged") => {
+                        let (interface, changed, _invalidated): (String, HashMap<String, OwnedValue>, Vec<String>) =
+                            msg.body().deserialize()?;
+                        if interface != PLAYER_INTERFACE {
+                            continue;
+                        }
+                        players.update(&sender, |player| player.update(&changed));
+                    }
+                    Some("Seeked") => {
+                        let position: i64 = msg.body().deserialize()?;
+                        players.update(&sender, |player| player.position = Some(micros_to_secs(position)));
+                    }
+                    _ => continue,
+                }
+            }
+            else => break,
+        }
+        send(players.to_dynval());
+    }
+    Ok(())
+}
+
+async fn add_player(con: &zbus::Connection, dbus: &DBusProxy<'_>, players: &mut Players, bus_name: BusName<'static>) {
+    let result: zbus::Result<_> = async {
+        let owner = dbus.get_name_owner(bus_name.clone()).await?;
+        let properties = PropertiesProxy::builder(con).destination(bus_name.clone())?.path(MPRIS_PATH)?.build().await?;
+        let mut data = PlayerData::default();
+        data.update(&properties.get_all(InterfaceName::from_static_str_unchecked(PLAYER_INTERFACE)).await?);
+        Ok((owner, data))
+    }
+    .await;
+    match result {
+        Ok((owner, data)) => players.add(bus_name[MPRIS_PREFIX.len()..].to_string(), owner, data),
+        Err(e) => log::warn!("Failed to read the state of media player {}: {}", bus_name, e),
+    }
+}
+
+fn micros_to_secs(micros: i64) -> f64 {
+    micros as f64 / 1_000_000_f64
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::private_bus::PrivateBus;
+    use zbus::interface;
+
+    struct TestPlayer {
+        status: String,
+        title: String,
+    }
+
+    #[interface(name = "org.mpris.MediaPlayer2.Player")]
+    impl TestPlayer {
+        #[zbus(property)]
+        fn playback_status(&self) -> String {
+            self.status.clone()
+        }
+
+        #[zbus(property)]
+        fn shuffle(&self) -> bool {
+            true
+        }
+
+        #[zbus(property)]
+        fn metadata(&self) -> HashMap<String, OwnedValue> {
+            let mut metadata = HashMap::new();
+            metadata.insert("xesam:title".to_string(), Value::from(self.title.as_str()).try_into().unwrap());
+            metadata.insert("xesam:artist".to_string(), Value::from(vec!["A", "B"]).try_into().unwrap());
+            metadata.insert("mpris:length".to_string(), Value::from(90_000_000_i64).try_into().unwrap());
+            metadata
+        }
+    }
+
+    #[test]
+    fn test_active_player() {
+        let mut players = Players::default();
+        let playing = PlayerData { status: "Playing".to_string(), ..PlayerData::default() };
+        let paused = PlayerData { status: "Paused".to_string(), ..PlayerData::default() };
+        players.add("a".to_string(), OwnedUniqueName::try_from(":1.1").unwrap(), paused.clone());
+        assert_eq!(players.active.as_deref(), Some("a"));
+        players.add("b".to_string(), OwnedUniqueName::try_from(":1.2").unwrap(), playing.clone());
+        assert_eq!(players.active.as_deref(), Some("b"));
+        players.add("c".to_string(), OwnedUniqueName::try_from(":1.3").unwrap(), paused);
+        assert_eq!(players.active.as_deref(), Some("b"));
+        players.update(&OwnedUniqueName::try_from(":1.1").unwrap(), |player| player.status = "Playing".to_string());
+        assert_eq!(players.active.as_deref(), Some("a"));
+        players.remove("a");
+        assert_eq!(players.active.as_deref(), Some("b"));
+    }
+
+    #[tokio::test]
+    async fn test_mpris_player() {
+        let Some(bus) = PrivateBus::start() else {
+            return;
+        };
+        let player_con = bus.connection().await;
+        player_con
+            .object_server()
+            .at(MPRIS_PATH, TestPlayer { status: "Paused".to_string(), title: "x".to_string() })
+            .await
+            .unwrap();
+        player_con.request_name("org.mpris.MediaPlayer2.test").await.unwrap();
+
+        let (send, mut recv) = tokio::sync::mpsc::unbounded_channel();
+        let eww_con = bus.connection().await;
+        tokio::spawn(async move { run(&eww_con, Box::new(move |value| send.send(value).unwrap())).await });
+        let mut next_value =
+            async || serde_json::from_str::<serde_json::Value>(&recv.recv().await.unwrap().as_string().unwrap()).unwrap();
+
+        let value = next_value().await;
+        assert_eq!(value["active"], "test");
+        assert_eq!(value["players"]["test"]["title"], "x");
+        assert_eq!(value["players"]["test"]["artist"], "A, B");
+        assert_eq!(value["players"]["test"]["length"], 90.0);
+        assert_eq!(value["players"]["test"]["status"], "Paused");
+        assert_eq!(value["players"]["test"]["shuffle"], true);
+
+        let player = player_con.object_server().interface::<_, TestPlayer>(MPRIS_PATH).await.unwrap();
+        player.get_mut().await.status = "Playing".to_string();
+        player.get().await.playback_status_changed(player.signal_emitter()).await.unwrap();
+        assert_eq!(next_value().await["players"]["test"]["status"], "Playing");
+
+        player_con.release_name("org.mpris.MediaPlayer2.test").await.unwrap();
+        assert_eq!(next_value().await, serde_json::json!({ "players": {}, "active": null }));
+    }
+}
@@ -1,11 +1,17 @@
-use std::process::Command;
+use std::{
+    collections::HashMap,
+    os::{fd::AsRawFd, unix::fs::OpenOptionsExt},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
 
 use anyhow::{Context, Result, anyhow, bail};
 use codespan_reporting::diagnostic::Severity;
 use eww_shared_util::{Span, VarName};
-use simplexpr::dynval::DynVal;
+use nix::{sys::signal, unistd::Pid};
+use simplexpr::{SimplExpr, dynval::DynVal, eval::EvalError};
 use yuck::{
-    config::script_var_definition::{ScriptVarDefinition, VarSource},
+    config::script_var_definition::{FileVar, FileVarFormat, ScriptVarDefinition, VarSource},
     error::DiagError,
     gen_diagnostic,
 };
@@ -30,21 +36,329 @@ pub fn initial_value(var: &ScriptVarDefinition) -> Result<DynVal> {
                 VarSource::Shell(span, command) => {
                     run_command(command).map_err(|e| anyhow!(create_script_var_failed_warn(*span, var.name(), &e.to_string())))
                 }
+                VarSource::Expression(expr) => run_expression(expr, &x.allowed_paths)
+                    .with_context(|| format!("Failed to compute initial value for {}", &var.name())),
             },
         },
 
         ScriptVarDefinition::Listen(var) => Ok(var.initial_value.clone()),
+        ScriptVarDefinition::Dbus(var) => Ok(var.initial_value.clone()),
+        ScriptVarDefinition::File(var) => match resolve_file_var_path(&var.path).and_then(|path| read_file_var(var, &path)) {
+            Ok(value) => Ok(value),
+            Err(err) => {
+                log::debug!("Using the initial value of {}, as its file could not be read: {:?}", var.name, err);
+                Ok(var.initial_value.clone())
+            }
+        },
+    }
+}
+
+/// Status of the script of a listen var, published as the JSON value of its `<name>__status` variable.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize)]
+pub struct ListenVarStatus {
+    pub running: bool,
+    /// Exit code of the last run of the script, `None` if it is still running or was killed by a signal
+    pub exit_code: Option<i32>,
+    pub restarts: u32,
+}
+
+impl ListenVarStatus {
+    pub fn to_dynval(self) -> DynVal {
+        DynVal::from(serde_json::to_string(&self).expect("Failed to serialize listen var status"))
     }
 }
 
+/// Resolve the path of a [`FileVar`], expanding a leading `~` and `*` wildcards in its components.
+/// If a wildcard matches multiple entries, the first one in alphabetical order is used.
+pub fn resolve_file_var_path(path: &str) -> Result<PathBuf> {
+    let path = expand_home(path)?;
+    if !path.to_string_lossy().contains('*') {
+        return Ok(path);
+    }
+    let mut resolved = PathBuf::new();
+    for component in path.iter() {
+        let component = component.to_string_lossy();
+        if !component.contains('*') {
+            resolved.push(component.as_ref());
+            continue;
+        }
+        let mut matches = resolved
+            .read_dir()
+            .with_context(|| format!("Couldn't read {}", resolved.display()))?
+            .filter_map(|entry| Some(entry.ok()?.file_name().to_string_lossy().to_string()))
+            .filter(|name| wildcard_matches(&component, name))
+            .collect::<Vec<_>>();
+        matches.sort();
+        let first = matches.into_iter().next().with_context(|| format!("Nothing matches {}", path.display()))?;
+        resolved.push(first);
+    }
+    Ok(resolved)
+}
+
+/// Expand a leading `~` in a path to the users home directory.
+fn expand_home(path: &str) -> Result<PathBuf> {
+    Ok(match path.strip_prefix("~/") {
+        Some(rest) => PathBuf::from(std::env::var("HOME").context("HOME is not set")?).join(rest),
+        None => PathBuf::from(path),
+    })
+}
+
+/// Check if `name` matches `pattern`, in which `*` matches any sequence of characters.
+fn wildcard_matches(pattern: &str, name: &str) -> bool {
+    match pattern.split_once('*') {
+        None => pattern == name,
+        Some((prefix, rest)) => {
+            let Some(name) = name.strip_prefix(prefix) else {
+                return false;
+            };
+            (0..=name.len()).filter(|i| name.is_char_boundary(*i)).any(|i| wildcard_matches(rest, &name[i..]))
+        }
+    }
+}
+
+/// Read the file of a [`FileVar`] at the already resolved `path`, and convert its contents according to the vars format.
+pub fn read_file_var(var: &FileVar, path: &Path) -> Result<DynVal> {
+    let content = std::fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let content = content.trim_end_matches('\n');
+    Ok(match var.format {
+        FileVarFormat::Text => DynVal::from(content),
+        FileVarFormat::Json => {
+            serde_json::from_str::<serde_json::Value>(content)
+                .with_context(|| format!("{} does not contain valid JSON", path.display()))?;
+            DynVal::from(content)
+        }
+        FileVarFormat::Lines => DynVal::from(serde_json::to_string(&content.lines().collect::<Vec<_>>())?),
+    })
+}
+
 /// Run a command and get the output
 pub fn run_command(cmd: &str) -> Result<DynVal> {
     log::debug!("Running command: {}", cmd);
-    let command = Command::new("/bin/sh").arg("-c").arg(cmd).output()?;
-    if !command.status.success() {
-        bail!("Failed with output:\n{}", String::from_utf8(command.stderr)?);
+    command_output_to_dynval(Command::new("/bin/sh").arg("-c").arg(cmd).output()?)
+}
+
+/// Run a command and get the output, without blocking. The command is killed if the returned future is dropped,
+/// which allows for timeouts.
+pub async fn run_command_async(cmd: &str) -> Result<DynVal> {
+    log::debug!("Running command: {}", cmd);
+    // the command runs in its own process group, so that pipelines and other children of the shell can be killed with it
+    let child = tokio::process::Command::new("/bin/sh")
+        .arg("-c")
+        .arg(cmd)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .process_group(0)
+        .kill_on_drop(true)
+        .spawn()?;
+    let mut kill_group = KillProcessGroupOnDrop(child.id());
+    let output = child.wait_with_output().await?;
+    kill_group.0 = None;
+    command_output_to_dynval(output)
+}
+
+/// Kills the process group with the given id when dropped, unless it was disarmed by setting it to `None`.
+struct KillProcessGroupOnDrop(Option<u32>);
+
+impl Drop for KillProcessGroupOnDrop {
+    fn drop(&mut self) {
+        if let Some(id) = self.0 {
+            log::debug!("Killing process group {}", id);
+            // there's no way to wait for a graceful exit here, so don't give it a chance to ignore the signal
+            let _ = signal::killpg(Pid::from_raw(id as i32), signal::SIGKILL);
+        }
     }
-    let output = String::from_utf8(command.stdout)?;
+}
+
+fn command_output_to_dynval(output: std::process::Output) -> Result<DynVal> {
+    if !output.status.success() {
+        bail!("Failed with output:\n{}", String::from_utf8(output.stderr)?);
+    }
+    let output = String::from_utf8(output.stdout)?;
     let output = output.trim_matches('\n');
     Ok(DynVal::from(output))
 }
+
+/// Evaluate the expression of a `:lang "simplexpr"` script, which has access to the [`script_function`]s.
+/// This blocks on file I/O, so it must not be called on the async runtime directly.
+///
+/// The file functions may only access files inside the `allowed_paths`, so without any allowed paths, scripts can't
+/// access the file system at all.
+pub fn run_expression(expr: &SimplExpr, allowed_paths: &[String]) -> Result<DynVal> {
+    log::debug!("Evaluating script: {}", expr);
+    Ok(expr.eval_with_functions(&HashMap::new(), &|name, args| script_function(name, args, allowed_paths))?)
+}
+
+/// Functions that are only available in scripts, as they have side effects or are too expensive to run on every update
+/// of a widget.
+fn script_function(name: &str, args: &[DynVal], allowed_paths: &[String]) -> Option<Result<DynVal, EvalError>> {
+    match name {
+        "read_file" | "read_lines" | "read_json" | "file_exists" | "list_dir" => {
+            Some(call_file_function(name, args, allowed_paths))
+        }
+        _ => None,
+    }
+}
+
+fn call_file_function(name: &str, args: &[DynVal], allowed_paths: &[String]) -> Result<DynVal, EvalError> {
+    let failed = |e: &dyn std::fmt::Display| EvalError::FunctionFailed(name.to_string(), e.to_string());
+    let [path] = args else {
+        return Err(EvalError::WrongArgCount(name.to_string()));
+    };
+    let path = PathBuf::from(path.as_string()?);
+    let not_allowed =
+        || failed(&format!("Access to {} is not allowed, add it to the `:allowed-paths` of the variable", path.display()));
+    let allowed_paths: Vec<PathBuf> =
+        allowed_paths.iter().filter_map(|allowed| expand_home(allowed).ok()?.canonicalize().ok()).collect();
+
+    // The path is opened first and then checked, so it can't be swapped out for a symlink between checking and reading.
+    // `O_PATH` only resolves the path without actually opening the file, which would have side effects for some files.
+    let file = match std::fs::OpenOptions::new().read(true).custom_flags(libc::O_PATH).open(&path) {
+        Ok(file) => file,
+        Err(_) if !is_path_allowed(&path, &allowed_paths) => return Err(not_allowed()),
+        Err(e) if name == "file_exists" && e.kind() == std::io::ErrorKind::NotFound => return Ok(DynVal::from(false)),
+        Err(e) => return Err(failed(&e)),
+    };
+    let fd_path = PathBuf::from(format!("/proc/self/fd/{}", file.as_raw_fd()));
+    let opened_path = std::fs::read_link(&fd_path).map_err(|e| failed(&e))?;
+    if !allowed_paths.iter().any(|allowed| opened_path.starts_with(allowed)) {
+        return Err(not_allowed());
+    }
+
+    // Reopening through `/proc/self/fd` opens the file that was checked, no matter what happened to `path` since.
+    let read = || std::fs::read_to_string(&fd_path).map_err(|e| failed(&e));
+    Ok(match name {
+        "read_file" => DynVal::from(read()?.trim_end_matches('\n')),
+        "read_lines" => DynVal::from(serde_json::Value::from(read()?.lines().collect::<Vec<_>>()).to_string()),
+        "read_json" => DynVal::from(serde_json::from_str::<serde_json::Value>(&read()?).map_err(|e| failed(&e))?.to_string()),
+        "file_exists" => DynVal::from(true),
+        "list_dir" => {
+            let mut names: Vec<String> = std::fs::read_dir(&fd_path)
+                .and_then(|entries| entries.map(|entry| Ok(entry?.file_name().to_string_lossy().to_string())).collect())
+                .map_err(|e: std::io::Error| failed(&e))?;
+            names.sort();
+            DynVal::from(serde_json::Value::from(names).to_string())
+        }
+        _ => unreachable!(),
+    })
+}
+
+/// Check if a `path` that can't be opened would be inside one of the canonicalized `allowed_paths`, so that errors don't
+/// reveal anything about files outside of them.
+fn is_path_allowed(path: &Path, allowed_paths: &[PathBuf]) -> bool {
+    let Some(path) = canonicalize_lenient(path) else {
+        return false;
+    };
+    allowed_paths.iter().any(|allowed| path.starts_with(allowed))
+}
+
+/// Canonicalize a path that may not exist yet, by canonicalizing its parent directory instead.
+fn canonicalize_lenient(path: &Path) -> Option<PathBuf> {
+    match path.canonicalize() {
+        Ok(path) => Some(path),
+        Err(_) => Some(path.parent()?.canonicalize().ok()?.join(path.file_name()?)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::{temp_dir, write_file};
+
+    #[test]
+    fn test_wildcard_matches() {
+        assert!(wildcard_matches("*", "intel_backlight"));
+        assert!(wildcard_matches("intel_*", "intel_backlight"));
+        assert!(wildcard_matches("*_backlight", "intel_backlight"));
+        assert!(wildcard_matches("a*c*e", "abcde"));
+        assert!(!wildcard_matches("a*c*e", "abcd"));
+        assert!(!wildcard_matches("amdgpu_*", "intel_backlight"));
+    }
+
+    #[test]
+    fn test_file_var() {
+        let dir = temp_dir("file-var");
+        write_file(dir.join("backlight/intel_backlight/brightness"), "512\n");
+        let resolved = resolve_file_var_path(&format!("{}/backlight/*/brightness", dir.display())).unwrap();
+        assert_eq!(resolved, dir.join("backlight/intel_backlight/brightness"));
+
+        let var = |format| FileVar {
+            name: VarName::from("foo"),
+            path: String::new(),
+            format,
+            initial_value: DynVal::from(""),
+            name_span: Span::DUMMY,
+        };
+        assert_eq!(read_file_var(&var(FileVarFormat::Text), &resolved).unwrap(), DynVal::from("512"));
+
+        std::fs::write(dir.join("state"), "a\nb\n").unwrap();
+        assert_eq!(read_file_var(&var(FileVarFormat::Lines), &dir.join("state")).unwrap(), DynVal::from(r#"["a","b"]"#));
+        assert!(read_file_var(&var(FileVarFormat::Json), &dir.join("state")).is_err());
+        std::fs::write(dir.join("state"), r#"{"a": 1}"#).unwrap();
+        assert_eq!(read_file_var(&var(FileVarFormat::Json), &dir.join("state")).unwrap(), DynVal::from(r#"{"a": 1}"#));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_command_timeout_kills_children() {
+        let dir = temp_dir("command-timeout");
+        let pid_file = dir.join("pid");
+        let command = format!("sleep 10 & echo $! > {}; wait", pid_file.display());
+        assert!(tokio::time::timeout(std::time::Duration::from_millis(200), run_command_async(&command)).await.is_err());
+        let stat_file = format!("/proc/{}/stat", std::fs::read_to_string(&pid_file).unwrap().trim());
+        // the killed child may briefly linger as a zombie until it is reaped
+        let is_dead = || std::fs::read_to_string(&stat_file).map_or(true, |stat| stat.split_whitespace().nth(2) == Some("Z"));
+        for _ in 0..50 {
+            if is_dead() {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(20));
+        }
+        assert!(is_dead());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_script_functions() {
+        let dir = temp_dir("script-functions");
+        write_file(dir.join("value"), "42\n");
+        write_file(dir.join("lines"), "a\nb\n");
+        write_file(dir.join("data.json"), r#"{"battery": {"level": 80}}"#);
+        let allowed_paths = vec![dir.display().to_string()];
+        let run = |code: &str| run_expression(&simplexpr::parse_string(0, 0, code).unwrap(), &allowed_paths);
+        let dir_str = dir.display();
+
+        assert_eq!(run(&format!(r#"read_file("{dir_str}/value") + 1"#)).unwrap(), DynVal::from(43));
+        assert_eq!(run(&format!(r#"read_lines("{dir_str}/lines")[1]"#)).unwrap(), DynVal::from("b"));
+        assert_eq!(run(&format!(r#"read_json("{dir_str}/data.json").battery.level"#)).unwrap(), DynVal::from(80));
+        assert_eq!(run(&format!(r#"file_exists("{dir_str}/missing")"#)).unwrap(), DynVal::from(false));
+        assert_eq!(run(&format!(r#"list_dir("{dir_str}")"#)).unwrap(), DynVal::from(r#"["data.json","lines","value"]"#));
+        assert!(run(&format!(r#"read_file("{dir_str}/missing")"#)).is_err());
+        assert!(run("round(1.25, 1)").is_ok());
+        let run_without_access = |code: &str| run_expression(&simplexpr::parse_string(0, 0, code).unwrap(), &[]);
+        assert!(run_without_access(&format!(r#"read_file("{dir_str}/value")"#)).is_err());
+        assert!(run_without_access(&format!(r#"file_exists("{dir_str}/missing")"#)).is_err());
+        assert!(run_without_access("round(1.25, 1)").is_ok());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_script_allowed_paths() {
+        let dir = temp_dir("script-allowed-paths");
+        write_file(dir.join("allowed/value"), "42");
+        write_file(dir.join("secret"), "hunter2");
+        std::os::unix::fs::symlink(dir.join("secret"), dir.join("allowed/link")).unwrap();
+        let allowed_paths = vec![format!("{}/allowed", dir.display())];
+        let run = |code: &str| run_expression(&simplexpr::parse_string(0, 0, code).unwrap(), &allowed_paths);
+        let dir_str = dir.display();
+
+        assert_eq!(run(&format!(r#"read_file("{dir_str}/allowed/value")"#)).unwrap(), DynVal::from("42"));
+        assert_eq!(run(&format!(r#"file_exists("{dir_str}/allowed/missing")"#)).unwrap(), DynVal::from(false));
+        assert!(run(&format!(r#"read_file("{dir_str}/secret")"#)).is_err());
+        assert!(run(&format!(r#"read_file("{dir_str}/allowed/../secret")"#)).is_err());
+        assert!(run(&format!(r#"file_exists("{dir_str}/allowed/../missing")"#)).is_err());
+        assert!(run(&format!(r#"read_file("{dir_str}/allowed/link")"#)).is_err());
+        assert!(run(&format!(r#"file_exists("{dir_str}/allowed/link")"#)).is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
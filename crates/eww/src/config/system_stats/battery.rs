@@ -0,0 +1,277 @@
+use anyhow::{Context, Result};
+use std::{
+    collections::HashMap,
+    path::Path,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use super::{read_number, read_trimmed};
+
+const POWER_SUPPLY_DIR: &str = "/sys/class/power_supply";
+
+#[derive(Debug, Default, Clone, PartialEq, serde::Serialize)]
+struct BatteryData {
+    capacity: i64,
+    status: String,
+    /// Energies in Wh
+    energy_now: Option<f64>,
+    energy_full: Option<f64>,
+    energy_full_design: Option<f64>,
+    /// Power in W
+    power_now: Option<f64>,
+    /// Times in seconds
+    time_to_empty: Option<u64>,
+    time_to_full: Option<u64>,
+    health_perc: Option<f64>,
+    cycle_count: Option<u64>,
+}
+
+#[derive(Debug, PartialEq, serde::Serialize)]
+struct Data {
+    #[serde(flatten)]
+    batteries: HashMap<String, BatteryData>,
+    total: BatteryData,
+    total_avg: f64,
+    ac_online: bool,
+}
+
+pub fn get_battery_capacity() -> Result<String> {
+    Ok(match read_power_supplies(Path::new(POWER_SUPPLY_DIR))? {
+        Some(data) => serde_json::to_string(&data).unwrap(),
+        None => String::new(),
+    })
+}
+
+/// Read all batteries and mains power supplies in `power_supply_dir` (usually `/sys/class/power_supply`).
+/// Returns `None` if there are no batteries whose energy can be determined.
+fn read_power_supplies(power_supply_dir: &Path) -> Result<Option<Data>> {
+    let mut batteries = HashMap::new();
+    let mut ac_online = false;
+    let power_supply_entries =
+        power_supply_dir.read_dir().with_context(|| format!("Couldn't read {} directory", power_supply_dir.display()))?;
+    for entry in power_supply_entries {
+        let entry = entry?.path();
+        if !entry.is_dir() {
+            continue;
+        }
+        if read_trimmed(&entry.join("type")).as_deref() == Some("Mains") {
+            ac_online |= read_number::<u8>(&entry.join("online")) == Some(1);
+        } else if let Some(battery) = read_battery(&entry)? {
+            batteries.insert(entry.file_name().context("Couldn't get filename")?.to_string_lossy().to_string(), battery);
+        }
+    }
+
+    let total = total_of(batteries.values());
+    let total_avg = match (total.energy_now, total.energy_full) {
+        (Some(energy_now), Some(energy_full)) if energy_full > 0_f64 => energy_now / energy_full * 100_f64,
+        _ => return Ok(None),
+    };
+    Ok(Some(Data { batteries, total, total_avg, ac_online }))
+}
+
+/// Read a single battery. Returns `None` if the power supply is not a battery.
+fn read_battery(entry: &Path) -> Result<Option<BatteryData>> {
+    let (Some(capacity), Some(status)) = (read_trimmed(&entry.join("capacity")), read_trimmed(&entry.join("status"))) else {
+        return Ok(None);
+    };
+    // sysfs reports energy in µWh, charge in µAh, voltage in µV, current in µA and power in µW
+    let read_scaled = |name: &str| read_number::<f64>(&entry.join(name)).map(|x| x / 1_000_000_f64);
+    let voltage = read_scaled("voltage_now");
+    let energy_or_charge = |name: &str| {
+        read_scaled(&format!("energy_{}", name)).or_else(|| Some(read_scaled(&format!("charge_{}", name))? * voltage?))
+    };
+
+    let energy_now = energy_or_charge("now");
+    let energy_full = energy_or_charge("full");
+    let energy_full_design = energy_or_charge("full_design");
+    if energy_now.is_none() || energy_full.is_none() {
+        static WARNED: AtomicBool = AtomicBool::new(false);
+        if !WARNED.swap(true, Ordering::Relaxed) {
+            log::warn!(
+                "Failed to get/calculate the energy of {}: the total values of the battery magic var may be incomplete",
+                entry.display()
+            );
+        }
+    }
+    let power_now = read_scaled("power_now").or_else(|| Some(read_scaled("current_now")? * voltage?)).map(f64::abs);
+    let (time_to_empty, time_to_full) = time_remaining(&status, energy_now, energy_full, power_now);
+
+    Ok(Some(BatteryData {
+        capacity: capacity.parse::<f64>()?.round() as i64,
+        status,
+        energy_now,
+        energy_full,
+        energy_full_design,
+        power_now,
+        time_to_empty,
+        time_to_full,
+        health_perc: health_perc(energy_full, energy_full_design),
+        cycle_count: read_number(&entry.join("cycle_count")),
+    }))
+}
+
+/// Combine multiple batteries into one, as if they were a single battery.
+fn total_of<'a>(batteries: impl Iterator<Item = &'a BatteryData> + Clone) -> BatteryData {
+    // batteries that don't report a value are skipped, instead of making the whole total unknown
+    let sum = |get: fn(&BatteryData) -> Option<f64>| {
+        batteries.clone().filter_map(get).fold(None, |acc, x| Some(acc.unwrap_or(0_f64) + x))
+    };
+    // values that are divided by each other are only summed over the batteries reporting both, so that a battery
+    // reporting only one of them can't skew the ratio, i.e. count towards energy_now but not towards energy_full
+    let sum_of_pairs = |get: fn(&BatteryData) -> (Option<f64>, Option<f64>)| {
+        batteries
+            .clone()
+            .filter_map(|x| match get(x) {
+                (Some(a), Some(b)) => Some((a, b)),
+                _ => None,
+            })
+            .fold(None, |acc, (a, b)| {
+                let (sum_a, sum_b) = acc.unwrap_or((0_f64, 0_f64));
+                Some((sum_a + a, sum_b + b))
+            })
+            .unzip()
+    };
+    let (energy_now, energy_full) = sum_of_pairs(|x| (x.energy_now, x.energy_full));
+    let energy_full_design = sum(|x| x.energy_full_design);
+    let power_now = sum(|x| x.power_now);
+    let (health_full, health_full_design) = sum_of_pairs(|x| (x.energy_full, x.energy_full_design));
+
+    // if any battery is being discharged or charged, the system as a whole is as well
+    let status = ["Discharging", "Charging"]
+        .into_iter()
+        .find(|status| batteries.clone().any(|x| &x.status == status))
+        .map(str::to_string)
+        .or_else(|| batteries.clone().next().map(|x| x.status.clone()))
+        .unwrap_or_default();
+    let (time_to_empty, time_to_full) = time_remaining(&status, energy_now, energy_full, power_now);
+
+    BatteryData {
+        capacity: match (energy_now, energy_full) {
+            (Some(energy_now), Some(energy_full)) if energy_full > 0_f64 => (energy_now / energy_full * 100_f64).round() as i64,
+            _ => 0,
+        },
+        status,
+        energy_now,
+        energy_full,
+        energy_full_design,
+        power_now,
+        time_to_empty,
+        time_to_full,
+        health_perc: health_perc(health_full, health_full_design),
+        cycle_count: None,
+    }
+}
+
+/// Compute the time in seconds until the battery is empty or full, depending on its status.
+fn time_remaining(
+    status: &str,
+    energy_now: Option<f64>,
+    energy_full: Option<f64>,
+    power_now: Option<f64>,
+) -> (Option<u64>, Option<u64>) {
+    let (Some(energy_now), Some(energy_full), Some(power_now)) = (energy_now, energy_full, power_now) else {
+        return (None, None);
+    };
+    if power_now <= 0_f64 {
+        return (None, None);
+    }
+    let hours_to_seconds = |hours: f64| (hours * 3600_f64).round() as u64;
+    match status {
+        "Discharging" => (Some(hours_to_seconds(energy_now / power_now)), None),
+        "Charging" => (None, Some(hours_to_seconds((energy_full - energy_now).max(0_f64) / power_now))),
+        _ => (None, None),
+    }
+}
+
+fn health_perc(energy_full: Option<f64>, energy_full_design: Option<f64>) -> Option<f64> {
+    match (energy_full, energy_full_design) {
+        (Some(energy_full), Some(energy_full_design)) if energy_full_design > 0_f64 => {
+            Some(energy_full / energy_full_design * 100_f64)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::{temp_dir, write_file};
+
+    #[test]
+    fn test_time_remaining() {
+        assert_eq!(time_remaining("Discharging", Some(30.0), Some(60.0), Some(15.0)), (Some(7200), None));
+        assert_eq!(time_remaining("Charging", Some(30.0), Some(60.0), Some(15.0)), (None, Some(7200)));
+        assert_eq!(time_remaining("Full", Some(60.0), Some(60.0), Some(0.0)), (None, None));
+        assert_eq!(time_remaining("Discharging", Some(30.0), Some(60.0), None), (None, None));
+    }
+
+    #[test]
+    fn test_power_supplies() {
+        let dir = temp_dir("power_supply");
+        // energy based battery
+        write_file(dir.join("BAT0/type"), "Battery\n");
+        write_file(dir.join("BAT0/capacity"), "50\n");
+        write_file(dir.join("BAT0/status"), "Discharging\n");
+        write_file(dir.join("BAT0/energy_now"), "20000000\n");
+        write_file(dir.join("BAT0/energy_full"), "40000000\n");
+        write_file(dir.join("BAT0/energy_full_design"), "50000000\n");
+        write_file(dir.join("BAT0/power_now"), "10000000\n");
+        write_file(dir.join("BAT0/cycle_count"), "120\n");
+        // charge based battery
+        write_file(dir.join("BAT1/type"), "Battery\n");
+        write_file(dir.join("BAT1/capacity"), "100\n");
+        write_file(dir.join("BAT1/status"), "Not charging\n");
+        write_file(dir.join("BAT1/charge_now"), "2000000\n");
+        write_file(dir.join("BAT1/charge_full"), "2000000\n");
+        write_file(dir.join("BAT1/charge_full_design"), "2000000\n");
+        write_file(dir.join("BAT1/voltage_now"), "10000000\n");
+        write_file(dir.join("BAT1/current_now"), "0\n");
+        // battery without energy_full, which must not count towards the total energy
+        write_file(dir.join("BAT2/type"), "Battery\n");
+        write_file(dir.join("BAT2/capacity"), "10\n");
+        write_file(dir.join("BAT2/status"), "Unknown\n");
+        write_file(dir.join("BAT2/energy_now"), "5000000\n");
+        write_file(dir.join("AC/type"), "Mains\n");
+        write_file(dir.join("AC/online"), "0\n");
+
+        let data = read_power_supplies(&dir).unwrap().unwrap();
+        assert_eq!(
+            data.batteries["BAT0"],
+            BatteryData {
+                capacity: 50,
+                status: "Discharging".to_string(),
+                energy_now: Some(20.0),
+                energy_full: Some(40.0),
+                energy_full_design: Some(50.0),
+                power_now: Some(10.0),
+                time_to_empty: Some(7200),
+                time_to_full: None,
+                health_perc: Some(80.0),
+                cycle_count: Some(120),
+            }
+        );
+        assert_eq!(data.batteries["BAT1"].energy_now, Some(20.0));
+        assert_eq!(data.batteries["BAT1"].power_now, Some(0.0));
+        assert_eq!(data.batteries["BAT1"].time_to_empty, None);
+        assert_eq!(
+            data.total,
+            BatteryData {
+                capacity: 67,
+                status: "Discharging".to_string(),
+                energy_now: Some(40.0),
+                energy_full: Some(60.0),
+                energy_full_design: Some(70.0),
+                power_now: Some(10.0),
+                time_to_empty: Some(14400),
+                time_to_full: None,
+                health_perc: Some(60.0 / 70.0 * 100.0),
+                cycle_count: None,
+            }
+        );
+        assert!(!data.ac_online);
+
+        write_file(dir.join("AC/online"), "1\n");
+        assert!(read_power_supplies(&dir).unwrap().unwrap().ac_online);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
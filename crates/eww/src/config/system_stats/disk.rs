@@ -0,0 +1,176 @@
+use once_cell::sync::Lazy;
+use serde_json::{Map, Value};
+use std::{collections::HashMap, fs::read_to_string, path::Path, sync::Mutex, time::Duration};
+
+use super::RefreshTime;
+
+const DISKSTATS_FILE: &str = "/proc/diskstats";
+/// /proc/diskstats always counts in sectors of 512 bytes, regardless of the actual sector size of the device
+const SECTOR_SIZE: u64 = 512;
+
+static DISKS: Lazy<Mutex<sysinfo::Disks>> = Lazy::new(|| Mutex::new(sysinfo::Disks::new_with_refreshed_list()));
+static DISK_IO: Lazy<Mutex<(RefreshTime, HashMap<String, DiskIoCounters>)>> =
+    Lazy::new(|| Mutex::new((RefreshTime::new(), read_diskstats(Path::new(DISKSTATS_FILE)))));
+
+/// The cumulative I/O counters of a block device, as found in /proc/diskstats.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct DiskIoCounters {
+    reads_completed: u64,
+    sectors_read: u64,
+    writes_completed: u64,
+    sectors_written: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+struct DiskIoRates {
+    /// Bytes per second
+    read_rate: f64,
+    write_rate: f64,
+    /// Operations per second
+    read_iops: f64,
+    write_iops: f64,
+}
+
+pub fn get_disks() -> String {
+    let mut disks = DISKS.lock().unwrap();
+    disks.refresh(true);
+
+    let io_rates = {
+        let (last_refresh, last_counters) = &mut *DISK_IO.lock().unwrap();
+        let counters = read_diskstats(Path::new(DISKSTATS_FILE));
+        let rates = io_rates(last_counters, &counters, last_refresh.next_refresh());
+        *last_counters = counters;
+        rates
+    };
+
+    let mut data: Map<String, Value> = disks
+        .iter()
+        .map(|c| {
+            let total_space = c.total_space();
+            let available_space = c.available_space();
+            let used_space = total_space - available_space;
+
+            let mut disk = serde_json::json!({
+                "name": c.name(),
+                "total": total_space,
+                "free": available_space,
+                "used": used_space,
+                "used_perc": (used_space as f32 / total_space as f32) * 100f32
+            });
+            if let Some(rates) = device_name(Path::new(c.name())).and_then(|device| io_rates.get(&device)) {
+                disk.as_object_mut().unwrap().extend(into_map(rates));
+            }
+            (c.mount_point().display().to_string(), disk)
+        })
+        .collect();
+    // mount points always start with a `/`, so they can't collide with device names
+    data.extend(io_rates.iter().map(|(device, rates)| (device.clone(), Value::Object(into_map(rates)))));
+    Value::Object(data).to_string()
+}
+
+fn into_map(rates: &DiskIoRates) -> Map<String, Value> {
+    match serde_json::to_value(rates) {
+        Ok(Value::Object(map)) => map,
+        _ => Map::new(),
+    }
+}
+
+/// Get the kernel name of the block device of a disk, i.e. `/dev/mapper/root` -> `dm-0`.
+fn device_name(disk_name: &Path) -> Option<String> {
+    let device = std::fs::canonicalize(disk_name).unwrap_or_else(|_| disk_name.to_path_buf());
+    Some(device.file_name()?.to_string_lossy().to_string())
+}
+
+/// Read the I/O counters of all block devices that have seen any I/O from `diskstats_file` (usually `/proc/diskstats`).
+fn read_diskstats(diskstats_file: &Path) -> HashMap<String, DiskIoCounters> {
+    read_to_string(diskstats_file).map(|content| parse_diskstats(&content)).unwrap_or_default()
+}
+
+fn parse_diskstats(content: &str) -> HashMap<String, DiskIoCounters> {
+    content
+        .lines()
+        .filter_map(|line| {
+            // major minor name reads_completed reads_merged sectors_read ms_reading writes_completed writes_merged sectors_written ...
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let field = |index: usize| fields.get(index)?.parse::<u64>().ok();
+            let counters = DiskIoCounters {
+                reads_completed: field(3)?,
+                sectors_read: field(5)?,
+                writes_completed: field(7)?,
+                sectors_written: field(9)?,
+            };
+            (counters.reads_completed + counters.writes_completed > 0).then(|| (fields[2].to_string(), counters))
+        })
+        .collect()
+}
+
+/// Compute the I/O rates of all devices present in both snapshots, normalized over `elapsed`.
+fn io_rates(
+    last: &HashMap<String, DiskIoCounters>,
+    current: &HashMap<String, DiskIoCounters>,
+    elapsed: Duration,
+) -> HashMap<String, DiskIoRates> {
+    let seconds = elapsed.as_secs_f64();
+    let per_second =
+        |last: u64, current: u64| if seconds > 0_f64 { current.saturating_sub(last) as f64 / seconds } else { 0_f64 };
+    current
+        .iter()
+        .filter_map(|(device, current)| {
+            let last = last.get(device)?;
+            Some((
+                device.clone(),
+                DiskIoRates {
+                    read_rate: per_second(last.sectors_read, current.sectors_read) * SECTOR_SIZE as f64,
+                    write_rate: per_second(last.sectors_written, current.sectors_written) * SECTOR_SIZE as f64,
+                    read_iops: per_second(last.reads_completed, current.reads_completed),
+                    write_iops: per_second(last.writes_completed, current.writes_completed),
+                },
+            ))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const DISKSTATS: &str = "\
+   7       0 loop0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0
+ 259       0 nvme0n1 1000 10 20000 300 500 5 8000 200 0 400 500 0 0 0 0 0 0
+ 259       1 nvme0n1p1 100 0 2000 30 50 0 800 20 0 40 50 0 0 0 0 0 0
+";
+
+    #[test]
+    fn test_parse_diskstats() {
+        let stats = parse_diskstats(DISKSTATS);
+        assert_eq!(stats.len(), 2);
+        assert_eq!(
+            stats["nvme0n1"],
+            DiskIoCounters { reads_completed: 1000, sectors_read: 20000, writes_completed: 500, sectors_written: 8000 }
+        );
+        assert!(!stats.contains_key("loop0"));
+    }
+
+    #[test]
+    fn test_io_rates() {
+        let last = parse_diskstats(DISKSTATS);
+        let mut current = last.clone();
+        current.insert(
+            "nvme0n1".to_string(),
+            DiskIoCounters { reads_completed: 1100, sectors_read: 24000, writes_completed: 700, sectors_written: 8000 },
+        );
+        current.insert(
+            "sda".to_string(),
+            DiskIoCounters { reads_completed: 1, sectors_read: 1, writes_completed: 1, sectors_written: 1 },
+        );
+
+        let rates = io_rates(&last, &current, Duration::from_secs(2));
+        assert_eq!(
+            rates["nvme0n1"],
+            DiskIoRates { read_rate: 2000_f64 * 512_f64, write_rate: 0_f64, read_iops: 50_f64, write_iops: 100_f64 }
+        );
+        assert_eq!(rates["nvme0n1p1"], DiskIoRates { read_rate: 0_f64, write_rate: 0_f64, read_iops: 0_f64, write_iops: 0_f64 });
+        // devices without a previous sample have no rate yet
+        assert!(!rates.contains_key("sda"));
+    }
+}
@@ -0,0 +1,404 @@
+use once_cell::sync::Lazy;
+use serde_json::{Map, Value};
+use std::{
+    collections::HashMap,
+    fs::read_to_string,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use super::{read_number, read_trimmed};
+
+#[cfg(feature = "nvidia")]
+use nvml_wrapper::{
+    Nvml,
+    enum_wrappers::device::{Clock, TemperatureSensor},
+};
+
+const DRM_DIR: &str = "/sys/class/drm";
+const AMD_VENDOR_ID: &str = "0x1002";
+const INTEL_VENDOR_ID: &str = "0x8086";
+
+static GPU_PROVIDERS: Lazy<Mutex<Vec<Box<dyn GpuProvider>>>> = Lazy::new(|| Mutex::new(default_providers()));
+
+/// Information on a single gpu. Values that a provider can not read are `None`.
+#[derive(Debug, Default, Clone, PartialEq, serde::Serialize)]
+pub struct GpuData {
+    pub vendor: &'static str,
+    pub name: Option<String>,
+    /// Usage of the gpu in percent
+    pub load: Option<u32>,
+    /// Used vram in bytes
+    pub vram_used: Option<u64>,
+    /// Total vram in bytes
+    pub vram_total: Option<u64>,
+    /// Clocks in MHz
+    pub freq_graphics_current: Option<u32>,
+    pub freq_graphics_max: Option<u32>,
+    pub freq_memory_current: Option<u32>,
+    pub freq_memory_max: Option<u32>,
+    /// Temperature in degree Celsius
+    pub temp: Option<f64>,
+    pub power_w: Option<f64>,
+}
+
+/// A source of [`GpuData`] for all gpus of a specific vendor.
+pub trait GpuProvider: Send {
+    /// Read the current data of all gpus this provider is responsible for.
+    fn read_gpus(&mut self) -> Vec<GpuData>;
+}
+
+fn default_providers() -> Vec<Box<dyn GpuProvider>> {
+    // Allow unused mut because we only need it if the nvidia feature is enabled
+    #[allow(unused_mut)]
+    let mut providers: Vec<Box<dyn GpuProvider>> = vec![
+        Box::new(AmdGpuProvider { drm_dir: PathBuf::from(DRM_DIR) }),
+        Box::new(IntelGpuProvider { drm_dir: PathBuf::from(DRM_DIR), last_rc6_samples: HashMap::new() }),
+    ];
+    #[cfg(feature = "nvidia")]
+    match Nvml::init() {
+        Ok(nvml) => providers.insert(0, Box::new(NvmlProvider(nvml))),
+        Err(e) => log::error!(
+            "Are you sure you have an nvidia gpu and the proprietary drivers installed? Failed to initialize NVML: {:?}",
+            e
+        ),
+    }
+    providers
+}
+
+/// Get all gpus as an array of [`GpuData`] objects, or in the old flat format of [`to_legacy_keys`].
+pub fn get_gpus(legacy_keys: bool) -> String {
+    let gpus: Vec<GpuData> = GPU_PROVIDERS.lock().unwrap().iter_mut().flat_map(|provider| provider.read_gpus()).collect();
+    if legacy_keys { Value::Object(to_legacy_keys(&gpus)).to_string() } else { serde_json::to_string(&gpus).unwrap() }
+}
+
+/// Convert the gpus into the old flat format, i.e. `{ "NVIDIA_GPU_LOAD_0": 20, "NVIDIA_GPU_LOAD_1": 5 }`.
+/// These are the keys the NVML-only implementation produced, and are used for the gpus of all vendors,
+/// so that widgets work the same on every machine. The index counts all gpus.
+fn to_legacy_keys(gpus: &[GpuData]) -> Map<String, Value> {
+    let mut data = Map::new();
+    for (index, gpu) in gpus.iter().enumerate() {
+        let values = [
+            ("LOAD", gpu.load.map(Value::from)),
+            ("VRAM_CURRENT", gpu.vram_used.map(Value::from)),
+            ("VRAM_MAX", gpu.vram_total.map(Value::from)),
+            ("FREQ_GRAPHICS_CURRENT", gpu.freq_graphics_current.map(Value::from)),
+            ("FREQ_GRAPHICS_MAX", gpu.freq_graphics_max.map(Value::from)),
+            ("FREQ_MEMORY_CURRENT", gpu.freq_memory_current.map(Value::from)),
+            ("FREQ_MEMORY_MAX", gpu.freq_memory_max.map(Value::from)),
+            ("TEMP", gpu.temp.map(Value::from)),
+        ];
+        for (key, value) in values {
+            if let Some(value) = value {
+                data.insert(format!("NVIDIA_GPU_{}_{}", key, index), value);
+            }
+        }
+    }
+    data
+}
+
+#[cfg(feature = "nvidia")]
+struct NvmlProvider(Nvml);
+
+#[cfg(feature = "nvidia")]
+impl GpuProvider for NvmlProvider {
+    fn read_gpus(&mut self) -> Vec<GpuData> {
+        let device_count = match self.0.device_count() {
+            Ok(count) => count,
+            Err(e) => {
+                log::error!("Failed to get NVML device count: {:?}", e);
+                return Vec::new();
+            }
+        };
+        (0..device_count)
+            .filter_map(|index| self.0.device_by_index(index).ok())
+            .map(|device| {
+                let memory_info = device.memory_info().ok();
+                GpuData {
+                    vendor: "nvidia",
+                    name: device.name().ok(),
+                    load: device.utilization_rates().ok().map(|util| util.gpu),
+                    vram_used: memory_info.as_ref().map(|mem| mem.used),
+                    vram_total: memory_info.as_ref().map(|mem| mem.total),
+                    freq_graphics_current: device.clock_info(Clock::Graphics).ok(),
+                    freq_graphics_max: device.max_clock_info(Clock::Graphics).ok(),
+                    freq_memory_current: device.clock_info(Clock::Memory).ok(),
+                    freq_memory_max: device.max_clock_info(Clock::Memory).ok(),
+                    temp: device.temperature(TemperatureSensor::Gpu).ok().map(f64::from),
+                    // NVML reports milliwatts
+                    power_w: device.power_usage().ok().map(|power| power as f64 / 1000_f64),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Reads amdgpu cards from the drm sysfs directory (usually `/sys/class/drm`).
+struct AmdGpuProvider {
+    drm_dir: PathBuf,
+}
+
+impl GpuProvider for AmdGpuProvider {
+    fn read_gpus(&mut self) -> Vec<GpuData> {
+        find_gpu_cards(&self.drm_dir, AMD_VENDOR_ID)
+            .into_iter()
+            .map(|card| {
+                let device = card.join("device");
+                let (freq_graphics_current, freq_graphics_max) =
+                    read_to_string(device.join("pp_dpm_sclk")).map(|x| parse_amd_dpm_clocks(&x)).unwrap_or_default();
+                let (freq_memory_current, freq_memory_max) =
+                    read_to_string(device.join("pp_dpm_mclk")).map(|x| parse_amd_dpm_clocks(&x)).unwrap_or_default();
+                let hwmon = find_hwmon_dir(&device);
+                GpuData {
+                    vendor: "amd",
+                    name: read_trimmed(&device.join("product_name")).or_else(|| card_name(&card)),
+                    load: read_number(&device.join("gpu_busy_percent")),
+                    vram_used: read_number(&device.join("mem_info_vram_used")),
+                    vram_total: read_number(&device.join("mem_info_vram_total")),
+                    freq_graphics_current,
+                    freq_graphics_max,
+                    freq_memory_current,
+                    freq_memory_max,
+                    // hwmon reports millidegrees and microwatts
+                    temp: hwmon.as_ref().and_then(|x| read_number::<f64>(&x.join("temp1_input"))).map(|x| x / 1000_f64),
+                    power_w: hwmon
+                        .as_ref()
+                        .and_then(|x| {
+                            read_number::<f64>(&x.join("power1_average")).or_else(|| read_number(&x.join("power1_input")))
+                        })
+                        .map(|x| x / 1_000_000_f64),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Reads i915 and xe cards from the drm sysfs directory (usually `/sys/class/drm`).
+///
+/// The load is derived from the RC6 (idle) residency since the last read,
+/// and is thus only reported from the second read on.
+struct IntelGpuProvider {
+    drm_dir: PathBuf,
+    last_rc6_samples: HashMap<PathBuf, (Instant, u64)>,
+}
+
+impl GpuProvider for IntelGpuProvider {
+    fn read_gpus(&mut self) -> Vec<GpuData> {
+        find_gpu_cards(&self.drm_dir, INTEL_VENDOR_ID)
+            .into_iter()
+            .map(|card| {
+                let (freq_graphics_current, freq_graphics_max, rc6_residency) = match read_number(&card.join("gt_cur_freq_mhz")) {
+                    // i915 exposes its files in the card directory itself
+                    Some(current_freq) => (
+                        Some(current_freq),
+                        read_number(&card.join("gt_RP0_freq_mhz")).or_else(|| read_number(&card.join("gt_max_freq_mhz"))),
+                        read_number(&card.join("power/rc6_residency_ms"))
+                            .or_else(|| read_number(&card.join("gt/gt0/rc6_residency_ms"))),
+                    ),
+                    // xe exposes them per tile and gt
+                    None => {
+                        let gt = card.join("device/tile0/gt0");
+                        (
+                            read_number(&gt.join("freq0/cur_freq")),
+                            read_number(&gt.join("freq0/rp0_freq")).or_else(|| read_number(&gt.join("freq0/max_freq"))),
+                            read_number(&gt.join("gtidle/idle_residency_ms")),
+                        )
+                    }
+                };
+
+                let load = rc6_residency.and_then(|rc6_residency| {
+                    let now = Instant::now();
+                    let (last_time, last_residency) = self.last_rc6_samples.insert(card.clone(), (now, rc6_residency))?;
+                    Some(rc6_busy_percent(rc6_residency.saturating_sub(last_residency), now.duration_since(last_time)))
+                });
+
+                GpuData {
+                    vendor: "intel",
+                    name: card_name(&card),
+                    load,
+                    freq_graphics_current,
+                    freq_graphics_max,
+                    ..GpuData::default()
+                }
+            })
+            .collect()
+    }
+}
+
+/// Find all `card*` entries in `drm_dir` whose device has the given PCI vendor id, sorted by card number.
+fn find_gpu_cards(drm_dir: &Path, vendor_id: &str) -> Vec<PathBuf> {
+    let Ok(entries) = drm_dir.read_dir() else {
+        return Vec::new();
+    };
+    let mut cards: Vec<(u32, PathBuf)> = entries
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let card_number = entry.file_name().to_str()?.strip_prefix("card")?.parse::<u32>().ok()?;
+            let vendor = read_to_string(entry.path().join("device/vendor")).ok()?;
+            (vendor.trim() == vendor_id).then_some((card_number, entry.path()))
+        })
+        .collect();
+    cards.sort_by_key(|(card_number, _)| *card_number);
+    cards.into_iter().map(|(_, card)| card).collect()
+}
+
+fn card_name(card: &Path) -> Option<String> {
+    card.file_name().map(|x| x.to_string_lossy().to_string())
+}
+
+fn find_hwmon_dir(device: &Path) -> Option<PathBuf> {
+    device.join("hwmon").read_dir().ok()?.filter_map(|entry| entry.ok()).map(|entry| entry.path()).next()
+}
+
+/// Parse the contents of a `pp_dpm_sclk` or `pp_dpm_mclk` file into the current and maximum clock in MHz.
+/// The active state is marked with a `*`, i.e.:
+/// ```text
+/// 0: 500Mhz
+/// 1: 800Mhz *
+/// 2: 1900Mhz
+/// ```
+fn parse_amd_dpm_clocks(content: &str) -> (Option<u32>, Option<u32>) {
+    let mut current = None;
+    let mut max = None;
+    for line in content.lines() {
+        let Some((_, value)) = line.split_once(':') else { continue };
+        let is_current = value.contains('*');
+        let Some(freq) =
+            value.trim().trim_end_matches('*').trim().to_lowercase().strip_suffix("mhz").and_then(|x| x.parse().ok())
+        else {
+            continue;
+        };
+        if is_current {
+            current = Some(freq);
+        }
+        max = max.max(Some(freq));
+    }
+    (current, max)
+}
+
+/// Compute the busy percentage of an intel gpu from the time it spent in RC6 (idle) during `elapsed`.
+fn rc6_busy_percent(rc6_residency_delta_ms: u64, elapsed: Duration) -> u32 {
+    let elapsed_ms = elapsed.as_millis() as f64;
+    if elapsed_ms == 0_f64 {
+        return 0;
+    }
+    let idle_perc = (rc6_residency_delta_ms as f64 / elapsed_ms * 100_f64).min(100_f64);
+    (100_f64 - idle_perc).round() as u32
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::{temp_dir, write_file};
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_amd_dpm_clocks() {
+        assert_eq!(parse_amd_dpm_clocks("0: 500Mhz\n1: 800Mhz *\n2: 1900Mhz\n"), (Some(800), Some(1900)));
+        assert_eq!(parse_amd_dpm_clocks("0: 96Mhz *\n"), (Some(96), Some(96)));
+        assert_eq!(parse_amd_dpm_clocks(""), (None, None));
+    }
+
+    #[test]
+    fn test_amd_gpus() {
+        let drm = temp_dir("amdgpu");
+        let device = drm.join("card1/device");
+        write_file(device.join("vendor"), "0x1002\n");
+        write_file(device.join("product_name"), "Radeon RX 7800 XT\n");
+        write_file(device.join("gpu_busy_percent"), "42\n");
+        write_file(device.join("mem_info_vram_used"), "1073741824\n");
+        write_file(device.join("mem_info_vram_total"), "8589934592\n");
+        write_file(device.join("pp_dpm_sclk"), "0: 500Mhz\n1: 2100Mhz *\n2: 2500Mhz\n");
+        write_file(device.join("pp_dpm_mclk"), "0: 96Mhz\n1: 1000Mhz *\n");
+        write_file(device.join("hwmon/hwmon3/temp1_input"), "54000\n");
+        write_file(device.join("hwmon/hwmon3/power1_average"), "35000000\n");
+        // connectors and cards of other vendors are ignored
+        write_file(drm.join("card1-DP-1/status"), "connected\n");
+        write_file(drm.join("card0/device/vendor"), "0x8086\n");
+
+        let mut provider = AmdGpuProvider { drm_dir: drm.clone() };
+        assert_eq!(
+            provider.read_gpus(),
+            vec![GpuData {
+                vendor: "amd",
+                name: Some("Radeon RX 7800 XT".to_string()),
+                load: Some(42),
+                vram_used: Some(1073741824),
+                vram_total: Some(8589934592),
+                freq_graphics_current: Some(2100),
+                freq_graphics_max: Some(2500),
+                freq_memory_current: Some(1000),
+                freq_memory_max: Some(1000),
+                temp: Some(54.0),
+                power_w: Some(35.0),
+            }]
+        );
+        std::fs::remove_dir_all(drm).unwrap();
+    }
+
+    #[test]
+    fn test_rc6_busy_percent() {
+        assert_eq!(rc6_busy_percent(2000, Duration::from_secs(2)), 0);
+        assert_eq!(rc6_busy_percent(500, Duration::from_secs(2)), 75);
+        assert_eq!(rc6_busy_percent(0, Duration::from_secs(2)), 100);
+        // residency counters may run slightly ahead of the wall clock
+        assert_eq!(rc6_busy_percent(2010, Duration::from_secs(2)), 0);
+        assert_eq!(rc6_busy_percent(0, Duration::ZERO), 0);
+    }
+
+    #[test]
+    fn test_intel_gpus() {
+        let drm = temp_dir("intelgpu");
+        write_file(drm.join("card0/device/vendor"), "0x8086\n");
+        write_file(drm.join("card0/gt_cur_freq_mhz"), "350\n");
+        write_file(drm.join("card0/gt_max_freq_mhz"), "1100\n");
+        write_file(drm.join("card0/gt_RP0_freq_mhz"), "1300\n");
+        write_file(drm.join("card0/power/rc6_residency_ms"), "1000\n");
+        let xe_gt = drm.join("card1/device/tile0/gt0");
+        write_file(drm.join("card1/device/vendor"), "0x8086\n");
+        write_file(xe_gt.join("freq0/cur_freq"), "800\n");
+        write_file(xe_gt.join("freq0/rp0_freq"), "2050\n");
+
+        let mut provider = IntelGpuProvider { drm_dir: drm.clone(), last_rc6_samples: HashMap::new() };
+        let i915 = GpuData {
+            vendor: "intel",
+            name: Some("card0".to_string()),
+            freq_graphics_current: Some(350),
+            freq_graphics_max: Some(1300),
+            ..GpuData::default()
+        };
+        let xe = GpuData {
+            vendor: "intel",
+            name: Some("card1".to_string()),
+            freq_graphics_current: Some(800),
+            freq_graphics_max: Some(2050),
+            ..GpuData::default()
+        };
+        assert_eq!(provider.read_gpus(), vec![i915.clone(), xe.clone()]);
+
+        let gpus = provider.read_gpus();
+        assert!(gpus[0].load.is_some());
+        assert_eq!(gpus[1], xe);
+        std::fs::remove_dir_all(drm).unwrap();
+    }
+
+    #[test]
+    fn test_legacy_keys() {
+        let gpus = [
+            GpuData { vendor: "nvidia", load: Some(20), vram_total: Some(1024), ..GpuData::default() },
+            GpuData { vendor: "amd", load: Some(5), temp: Some(40.0), ..GpuData::default() },
+            GpuData { vendor: "nvidia", load: Some(30), ..GpuData::default() },
+        ];
+        assert_eq!(
+            Value::Object(to_legacy_keys(&gpus)),
+            json!({
+                "NVIDIA_GPU_LOAD_0": 20,
+                "NVIDIA_GPU_VRAM_MAX_0": 1024,
+                "NVIDIA_GPU_LOAD_1": 5,
+                "NVIDIA_GPU_TEMP_1": 40.0,
+                "NVIDIA_GPU_LOAD_2": 30,
+            })
+        );
+    }
+}
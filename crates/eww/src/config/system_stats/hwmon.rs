@@ -0,0 +1,282 @@
+use once_cell::sync::Lazy;
+use serde_json::{Map, Value, json};
+use std::{collections::BTreeSet, path::Path, sync::Mutex};
+
+#[cfg(feature = "nvidia")]
+use nvml_wrapper::Nvml;
+
+use super::{read_number, read_trimmed};
+
+const HWMON_DIR: &str = "/sys/class/hwmon";
+
+static COMPONENTS: Lazy<Mutex<sysinfo::Components>> = Lazy::new(|| Mutex::new(sysinfo::Components::new_with_refreshed_list()));
+
+/// A temperature sensor, with all values in degree Celsius.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+struct TemperatureData {
+    temp: Option<f64>,
+    max: Option<f64>,
+    critical: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+struct FanData {
+    rpm: Option<u64>,
+    min: Option<u64>,
+    max: Option<u64>,
+    /// Raw pwm duty cycle, from 0 to 255
+    pwm: Option<u8>,
+    pwm_perc: Option<f64>,
+}
+
+/// Get the temperatures grouped by chip, or in the old flat format of `<LABEL>: "<temperature>"` entries read through sysinfo.
+pub fn get_temperatures(legacy_keys: bool) -> String {
+    // Allow unused mut because we only need it if the nvidia feature is enabled
+    #[allow(unused_mut)]
+    let mut temps: Map<String, Value> = if legacy_keys {
+        let mut components = COMPONENTS.lock().unwrap();
+        components.refresh(true);
+        components
+            .iter()
+            .map(|c| {
+                (
+                    c.label().to_uppercase().replace(' ', "_"),
+                    if c.temperature().is_none() { Value::Null } else { Value::from(format!("{:.1}", c.temperature().unwrap())) },
+                )
+            })
+            .collect()
+    } else {
+        read_temperatures(Path::new(HWMON_DIR))
+    };
+
+    #[cfg(feature = "nvidia")]
+    if let Some(gpu_temps) = get_all_nvidia_gpu_temperatures() {
+        let mut nvidia_temps = Map::new();
+        for (index, gpu_temp) in gpu_temps.into_iter().enumerate() {
+            let gpu_temp = if gpu_temp.is_nan() { Value::Null } else { Value::from(gpu_temp) };
+            if legacy_keys {
+                temps.insert(format!("NVIDIA_GPU_{}", index), gpu_temp);
+            } else {
+                nvidia_temps.insert(format!("gpu{}", index), json!({ "temp": gpu_temp, "max": null, "critical": null }));
+            }
+        }
+        if !nvidia_temps.is_empty() {
+            temps.insert("nvidia".to_string(), Value::Object(nvidia_temps));
+        }
+    }
+
+    serde_json::to_string(&json!(temps)).unwrap()
+}
+
+/// Read the temperature sensors of all chips in `hwmon_dir` (usually `/sys/class/hwmon`), see [`read_chips`].
+fn read_temperatures(hwmon_dir: &Path) -> Map<String, Value> {
+    read_chips(
+        hwmon_dir,
+        "temp",
+        |file_name| file_name.strip_prefix("temp")?.strip_suffix("_input"),
+        |chip_dir, index| {
+            // hwmon reports millidegrees
+            let read_temp =
+                |file: &str| read_number::<f64>(&chip_dir.join(format!("temp{}_{}", index, file))).map(|x| x / 1000_f64);
+            json!(TemperatureData { temp: read_temp("input"), max: read_temp("max"), critical: read_temp("crit") })
+        },
+    )
+}
+
+#[cfg(feature = "nvidia")]
+fn get_all_nvidia_gpu_temperatures() -> Option<Vec<f64>> {
+    let nvml = match Nvml::init() {
+        Ok(nvml) => nvml,
+        Err(e) => {
+            log::error!(
+                "Are you shure you have nvidia gpu and proprietary drivers installed? \
+              Failed to initialize NVML: {:?}",
+                e
+            );
+            return None;
+        }
+    };
+
+    let device_count = match nvml.device_count() {
+        Ok(count) => {
+            if count == 0 {
+                log::warn!("NVML was initialized, but no devices were found.");
+                return None;
+            }
+            count
+        }
+        Err(e) => {
+            log::error!("Failed to get NVML device count: {:?}", e);
+            return None;
+        }
+    };
+
+    let mut gpu_temps = Vec::new();
+    for i in 0..device_count {
+        if let Ok(device) = nvml.device_by_index(i) {
+            if let Ok(temp) = device.temperature(nvml_wrapper::enum_wrappers::device::TemperatureSensor::Gpu) {
+                gpu_temps.push(temp as f64);
+            } else {
+                gpu_temps.push(f64::NAN);
+            }
+        }
+    }
+
+    Some(gpu_temps)
+}
+
+pub fn get_fans() -> String {
+    Value::Object(read_fans(Path::new(HWMON_DIR))).to_string()
+}
+
+/// Read the fans of all chips in `hwmon_dir` (usually `/sys/class/hwmon`), see [`read_chips`].
+fn read_fans(hwmon_dir: &Path) -> Map<String, Value> {
+    read_chips(
+        hwmon_dir,
+        "fan",
+        // fanN_input and pwmN share their index
+        |file_name| {
+            file_name.strip_prefix("fan").and_then(|x| x.strip_suffix("_input")).or_else(|| file_name.strip_prefix("pwm"))
+        },
+        |chip_dir, index| {
+            let pwm = read_number::<u8>(&chip_dir.join(format!("pwm{}", index)));
+            json!(FanData {
+                rpm: read_number(&chip_dir.join(format!("fan{}_input", index))),
+                min: read_number(&chip_dir.join(format!("fan{}_min", index))),
+                max: read_number(&chip_dir.join(format!("fan{}_max", index))),
+                pwm,
+                pwm_perc: pwm.map(|pwm| pwm as f64 / 255_f64 * 100_f64),
+            })
+        },
+    )
+}
+
+/// Read the sensors of all chips in `hwmon_dir`, grouped by the name of their chip.
+/// `sensor_index` gets the index of a sensor from the name of one of its files, i.e. `1` from `temp1_input`,
+/// and `read_sensor` reads the data of the sensor with the given index.
+///
+/// Sensors are keyed by their `<prefix>N_label`, or `<prefix>N` if they don't have one. Chips without any sensors are left out.
+/// Chips that share their name with another one, like two nvme drives, are keyed by their name and hwmon device from
+/// the second one on, i.e. `nvme_hwmon3`. Likewise, sensors sharing their label with another one on the same chip
+/// are keyed by their label and `<prefix>N`, i.e. `AUXTIN_temp4`.
+fn read_chips(
+    hwmon_dir: &Path,
+    prefix: &str,
+    sensor_index: impl Fn(&str) -> Option<&str>,
+    read_sensor: impl Fn(&Path, u32) -> Value,
+) -> Map<String, Value> {
+    let Ok(entries) = hwmon_dir.read_dir() else {
+        return Map::new();
+    };
+    let mut chip_dirs: Vec<_> = entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()).collect();
+    // sort numerically, so that hwmon10 comes after hwmon9
+    chip_dirs.sort_by_key(|chip_dir| {
+        let name = chip_dir.file_name().unwrap_or_default().to_string_lossy().to_string();
+        (name.trim_start_matches(|c: char| !c.is_ascii_digit()).parse::<u32>().ok(), name)
+    });
+
+    let mut chips = Map::new();
+    for chip_dir in chip_dirs {
+        let Ok(files) = chip_dir.read_dir() else { continue };
+        let indices: BTreeSet<u32> =
+            files.filter_map(|file| sensor_index(&file.ok()?.file_name().to_string_lossy())?.parse().ok()).collect();
+        if indices.is_empty() {
+            continue;
+        }
+        let mut sensors = Map::new();
+        for index in indices {
+            let sensor_name = format!("{}{}", prefix, index);
+            let mut label = read_trimmed(&chip_dir.join(format!("{}_label", sensor_name))).unwrap_or_else(|| sensor_name.clone());
+            if sensors.contains_key(&label) {
+                label = format!("{}_{}", label, sensor_name);
+            }
+            sensors.insert(label, read_sensor(&chip_dir, index));
+        }
+
+        let chip_dir_name = chip_dir.file_name().unwrap_or_default().to_string_lossy().to_string();
+        let mut chip_name = read_trimmed(&chip_dir.join("name")).unwrap_or_else(|| chip_dir_name.clone());
+        if chips.contains_key(&chip_name) {
+            chip_name = format!("{}_{}", chip_name, chip_dir_name);
+        }
+        chips.insert(chip_name, Value::Object(sensors));
+    }
+    chips
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::{temp_dir, write_file};
+
+    #[test]
+    fn test_temperatures() {
+        let dir = temp_dir("hwmon-temps");
+        write_file(dir.join("hwmon0/name"), "coretemp\n");
+        write_file(dir.join("hwmon0/temp1_input"), "50000\n");
+        write_file(dir.join("hwmon0/temp1_label"), "Package id 0\n");
+        write_file(dir.join("hwmon0/temp1_max"), "80000\n");
+        write_file(dir.join("hwmon0/temp1_crit"), "100000\n");
+        write_file(dir.join("hwmon0/temp2_input"), "45000\n");
+        // neither must two sensors sharing a label on the same chip
+        write_file(dir.join("hwmon1/name"), "nct6775\n");
+        write_file(dir.join("hwmon1/temp3_input"), "30000\n");
+        write_file(dir.join("hwmon1/temp3_label"), "AUXTIN\n");
+        write_file(dir.join("hwmon1/temp4_input"), "31000\n");
+        write_file(dir.join("hwmon1/temp4_label"), "AUXTIN\n");
+        // two chips sharing a name, like two nvme drives, must not overwrite each other
+        write_file(dir.join("hwmon2/name"), "nvme\n");
+        write_file(dir.join("hwmon2/temp1_input"), "38850\n");
+        write_file(dir.join("hwmon2/temp1_label"), "Composite\n");
+        write_file(dir.join("hwmon10/name"), "nvme\n");
+        write_file(dir.join("hwmon10/temp1_input"), "41850\n");
+        write_file(dir.join("hwmon10/temp1_label"), "Composite\n");
+        // chips without temperature sensors are left out
+        write_file(dir.join("hwmon3/name"), "thinkpad\n");
+        write_file(dir.join("hwmon3/fan1_input"), "2400\n");
+
+        assert_eq!(
+            Value::Object(read_temperatures(&dir)),
+            json!({
+                "coretemp": {
+                    "Package id 0": { "temp": 50.0, "max": 80.0, "critical": 100.0 },
+                    "temp2": { "temp": 45.0, "max": null, "critical": null },
+                },
+                "nct6775": {
+                    "AUXTIN": { "temp": 30.0, "max": null, "critical": null },
+                    "AUXTIN_temp4": { "temp": 31.0, "max": null, "critical": null },
+                },
+                "nvme": { "Composite": { "temp": 38.85, "max": null, "critical": null } },
+                "nvme_hwmon10": { "Composite": { "temp": 41.85, "max": null, "critical": null } },
+            })
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_fans() {
+        let dir = temp_dir("hwmon-fans");
+        write_file(dir.join("hwmon0/name"), "coretemp\n");
+        write_file(dir.join("hwmon0/temp1_input"), "45000\n");
+        write_file(dir.join("hwmon1/name"), "thinkpad\n");
+        write_file(dir.join("hwmon1/fan1_input"), "2400\n");
+        write_file(dir.join("hwmon1/fan1_label"), "CPU Fan\n");
+        write_file(dir.join("hwmon1/pwm1"), "255\n");
+        write_file(dir.join("hwmon1/fan2_input"), "0\n");
+        write_file(dir.join("hwmon2/name"), "thinkpad\n");
+        write_file(dir.join("hwmon2/pwm1"), "51\n");
+
+        assert_eq!(
+            Value::Object(read_fans(&dir)),
+            json!({
+                "thinkpad": {
+                    "CPU Fan": { "rpm": 2400, "min": null, "max": null, "pwm": 255, "pwm_perc": 100.0 },
+                    "fan2": { "rpm": 0, "min": null, "max": null, "pwm": null, "pwm_perc": null },
+                },
+                "thinkpad_hwmon2": {
+                    "fan1": { "rpm": null, "min": null, "max": null, "pwm": 51, "pwm_perc": 20.0 },
+                },
+            })
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,108 @@
+use crate::util::IterAverage;
+use once_cell::sync::Lazy;
+use std::{fs::read_to_string, path::Path, sync::Mutex};
+use sysinfo::System;
+
+mod battery;
+mod disk;
+mod gpu;
+mod hwmon;
+mod net;
+mod procs;
+pub use battery::get_battery_capacity;
+pub use disk::get_disks;
+pub use gpu::get_gpus;
+pub use hwmon::{get_fans, get_temperatures};
+pub use net::net;
+pub use procs::get_processes;
+
+struct RefreshTime(std::time::Instant);
+impl RefreshTime {
+    pub fn new() -> Self {
+        Self(std::time::Instant::now())
+    }
+
+    pub fn next_refresh(&mut self) -> std::time::Duration {
+        let now = std::time::Instant::now();
+        let duration = now.duration_since(self.0);
+        self.0 = now;
+        duration
+    }
+}
+
+static SYSTEM: Lazy<Mutex<System>> = Lazy::new(|| Mutex::new(System::new()));
+
+pub fn get_ram() -> String {
+    let mut system = SYSTEM.lock().unwrap();
+    system.refresh_memory();
+
+    let total_memory = system.total_memory();
+    let available_memory = system.available_memory();
+    let used_memory = total_memory as f32 - available_memory as f32;
+    serde_json::json!({
+        "total_mem": total_memory,
+        "free_mem": system.free_memory(),
+        "total_swap": system.total_swap(),
+        "free_swap": system.free_swap(),
+        "available_mem": available_memory,
+        "used_mem": used_memory,
+        "used_mem_perc": (used_memory / total_memory as f32) * 100f32,
+    })
+    .to_string()
+}
+
+pub fn get_cpus() -> String {
+    let mut system = SYSTEM.lock().unwrap();
+    system.refresh_cpu_specifics(sysinfo::CpuRefreshKind::everything());
+    let cpus = system.cpus();
+    serde_json::json!({
+        "cores": cpus.iter()
+            .map(|a| {
+                serde_json::json!({
+                    "core": a.name(),
+                    "freq": a.frequency(),
+                    "usage": a.cpu_usage() as i64
+                })
+            }).collect::<Vec<_>>(),
+        "avg": cpus.iter().map(|a| a.cpu_usage()).avg()
+    })
+    .to_string()
+}
+
+pub fn get_sys_info() -> String {
+    let cpu_count = {
+        let mut system = SYSTEM.lock().unwrap();
+        if system.cpus().is_empty() {
+            system.refresh_cpu_list(sysinfo::CpuRefreshKind::nothing());
+        }
+        system.cpus().len()
+    };
+    let load_avg = System::load_average();
+    serde_json::json!({
+        "load_avg_1": load_avg.one,
+        "load_avg_5": load_avg.five,
+        "load_avg_15": load_avg.fifteen,
+        "uptime": System::uptime(),
+        "boot_time": System::boot_time(),
+        "hostname": System::host_name(),
+        "kernel": System::kernel_version(),
+        "os": System::name(),
+        "os_version": System::os_version(),
+        "user": std::env::var("USER").or_else(|_| std::env::var("LOGNAME")).ok(),
+        "cpus": cpu_count,
+        "physical_cores": System::physical_core_count(),
+    })
+    .to_string()
+}
+
+pub fn get_time() -> String {
+    chrono::offset::Utc::now().timestamp().to_string()
+}
+
+fn read_trimmed(path: &Path) -> Option<String> {
+    read_to_string(path).ok().map(|x| x.trim().to_string())
+}
+
+fn read_number<T: std::str::FromStr>(path: &Path) -> Option<T> {
+    read_to_string(path).ok()?.trim().parse().ok()
+}
@@ -0,0 +1,94 @@
+use once_cell::sync::Lazy;
+use std::{net::IpAddr, path::Path, sync::Mutex, time::Duration};
+
+use super::{RefreshTime, read_trimmed};
+
+const NET_DIR: &str = "/sys/class/net";
+
+static NETWORKS: Lazy<Mutex<(RefreshTime, sysinfo::Networks)>> =
+    Lazy::new(|| Mutex::new((RefreshTime::new(), sysinfo::Networks::new_with_refreshed_list())));
+
+/// Link information of an interface that sysinfo does not provide.
+#[derive(Debug, PartialEq)]
+struct LinkInfo {
+    operstate: Option<String>,
+    wireless: bool,
+}
+
+pub fn net() -> String {
+    let (last_refresh, networks) = &mut *NETWORKS.lock().unwrap();
+
+    networks.refresh(true);
+    let elapsed = last_refresh.next_refresh();
+
+    networks
+        .iter()
+        .map(|(name, data)| {
+            let up_rate = per_second(data.transmitted(), elapsed);
+            let down_rate = per_second(data.received(), elapsed);
+            let addresses = |is_v4: bool| {
+                data.ip_networks().iter().filter(|x| x.addr.is_ipv4() == is_v4).map(|x| x.addr).collect::<Vec<IpAddr>>()
+            };
+            let link = read_link_info(Path::new(NET_DIR), name);
+            (
+                name,
+                serde_json::json!({
+                    "NET_UP": up_rate,
+                    "NET_DOWN": down_rate,
+                    "up_rate": up_rate,
+                    "down_rate": down_rate,
+                    "total_up": data.total_transmitted(),
+                    "total_down": data.total_received(),
+                    "ipv4": addresses(true),
+                    "ipv6": addresses(false),
+                    "mac": data.mac_address().to_string(),
+                    "mtu": data.mtu(),
+                    "operstate": link.operstate,
+                    "wireless": link.wireless,
+                }),
+            )
+        })
+        .collect::<serde_json::Value>()
+        .to_string()
+}
+
+/// Normalize a byte count collected over `elapsed` to bytes per second,
+/// so that the value does not depend on how often the variable is polled.
+fn per_second(bytes: u64, elapsed: Duration) -> f64 {
+    if elapsed.is_zero() { 0_f64 } else { bytes as f64 / elapsed.as_secs_f64() }
+}
+
+/// Read the link information of an interface from `net_dir` (usually `/sys/class/net`).
+fn read_link_info(net_dir: &Path, interface: &str) -> LinkInfo {
+    let interface_dir = net_dir.join(interface);
+    LinkInfo {
+        operstate: read_trimmed(&interface_dir.join("operstate")),
+        wireless: interface_dir.join("wireless").exists() || interface_dir.join("phy80211").exists(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::{temp_dir, write_file};
+
+    #[test]
+    fn test_per_second() {
+        assert_eq!(per_second(1000, Duration::from_secs(2)), 500_f64);
+        assert_eq!(per_second(1000, Duration::from_millis(500)), 2000_f64);
+        assert_eq!(per_second(1000, Duration::ZERO), 0_f64);
+    }
+
+    #[test]
+    fn test_link_info() {
+        let dir = temp_dir("net");
+        write_file(dir.join("wlan0/operstate"), "up\n");
+        write_file(dir.join("wlan0/wireless/link"), "0\n");
+        write_file(dir.join("eth0/operstate"), "down\n");
+
+        assert_eq!(read_link_info(&dir, "wlan0"), LinkInfo { operstate: Some("up".to_string()), wireless: true });
+        assert_eq!(read_link_info(&dir, "eth0"), LinkInfo { operstate: Some("down".to_string()), wireless: false });
+        assert_eq!(read_link_info(&dir, "lo"), LinkInfo { operstate: None, wireless: false });
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,142 @@
+use once_cell::sync::Lazy;
+use std::{collections::HashMap, sync::Mutex};
+use sysinfo::{ProcessRefreshKind, ProcessStatus, ProcessesToUpdate, Uid, UpdateKind, Users};
+
+use super::SYSTEM;
+
+/// Number of processes listed in `top_cpu` and `top_mem`
+const TOP_PROCESS_COUNT: usize = 10;
+
+/// Names of users by their id. Ids without a user, such as those of containers, are cached as `None`,
+/// so that they don't cause the list of users to be reread on every poll.
+static USER_NAMES: Lazy<Mutex<HashMap<Uid, Option<String>>>> = Lazy::new(Default::default);
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+struct ProcessData {
+    pid: u32,
+    name: String,
+    cmd: String,
+    /// Usage in percent of a single core
+    cpu: f32,
+    /// Resident set size in bytes
+    rss: u64,
+    user: Option<String>,
+}
+
+pub fn get_processes() -> String {
+    let mut system = SYSTEM.lock().unwrap();
+    system.refresh_processes_specifics(
+        ProcessesToUpdate::All,
+        true,
+        ProcessRefreshKind::nothing()
+            .with_cpu()
+            .with_memory()
+            .with_cmd(UpdateKind::OnlyIfNotSet)
+            .with_user(UpdateKind::OnlyIfNotSet),
+    );
+
+    // sysinfo may list threads as well, which we don't want to count as separate processes
+    let all_processes: Vec<_> = system.processes().values().filter(|process| process.thread_kind().is_none()).collect();
+    let mut user_names = USER_NAMES.lock().unwrap();
+    cache_user_names(&mut user_names, all_processes.iter().filter_map(|process| process.user_id()), || {
+        Users::new_with_refreshed_list().iter().map(|user| (user.id().clone(), user.name().to_string())).collect()
+    });
+
+    let mut running = 0;
+    let mut processes = Vec::new();
+    for process in all_processes {
+        if process.status() == ProcessStatus::Run {
+            running += 1;
+        }
+        let user = process.user_id().and_then(|uid| user_names.get(uid).cloned().flatten());
+        processes.push(ProcessData {
+            pid: process.pid().as_u32(),
+            name: process.name().to_string_lossy().to_string(),
+            cmd: process.cmd().iter().map(|arg| arg.to_string_lossy()).collect::<Vec<_>>().join(" "),
+            cpu: process.cpu_usage(),
+            rss: process.memory(),
+            user,
+        });
+    }
+
+    serde_json::json!({
+        "top_cpu": top_by(&processes, |a, b| b.cpu.total_cmp(&a.cpu)),
+        "top_mem": top_by(&processes, |a, b| b.rss.cmp(&a.rss)),
+        "total": processes.len(),
+        "running": running,
+    })
+    .to_string()
+}
+
+/// Add the names of all `uids` that aren't cached yet to `user_names`, loading the list of users at most once.
+fn cache_user_names<'a>(
+    user_names: &mut HashMap<Uid, Option<String>>,
+    uids: impl Iterator<Item = &'a Uid>,
+    load_users: impl FnOnce() -> HashMap<Uid, String>,
+) {
+    let missing: Vec<&Uid> = uids.filter(|uid| !user_names.contains_key(uid)).collect();
+    if missing.is_empty() {
+        return;
+    }
+    let users = load_users();
+    for uid in missing {
+        user_names.insert(uid.clone(), users.get(uid).cloned());
+    }
+}
+
+/// Get the first [`TOP_PROCESS_COUNT`] processes when sorted with the given comparison function.
+fn top_by(processes: &[ProcessData], compare: fn(&ProcessData, &ProcessData) -> std::cmp::Ordering) -> Vec<ProcessData> {
+    let mut processes = processes.to_vec();
+    processes.sort_by(compare);
+    processes.truncate(TOP_PROCESS_COUNT);
+    processes
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_top_by() {
+        let processes: Vec<ProcessData> = (0..15)
+            .map(|i| ProcessData {
+                pid: i,
+                name: format!("proc{}", i),
+                cmd: String::new(),
+                cpu: (i % 5) as f32,
+                rss: 1000 - i as u64,
+                user: None,
+            })
+            .collect();
+
+        let top_cpu = top_by(&processes, |a, b| b.cpu.total_cmp(&a.cpu));
+        assert_eq!(top_cpu.len(), TOP_PROCESS_COUNT);
+        assert!(top_cpu.windows(2).all(|x| x[0].cpu >= x[1].cpu));
+        assert_eq!(top_cpu[0].cpu, 4_f32);
+
+        let top_mem = top_by(&processes, |a, b| b.rss.cmp(&a.rss));
+        assert_eq!(top_mem.iter().map(|x| x.pid).collect::<Vec<_>>(), (0..10).collect::<Vec<_>>());
+
+        assert!(top_by(&[], |a, b| b.rss.cmp(&a.rss)).is_empty());
+    }
+
+    #[test]
+    fn test_cache_user_names() {
+        let uid = |id: usize| Uid::try_from(id).unwrap();
+        let mut user_names = HashMap::new();
+        let loads = std::cell::Cell::new(0);
+        let load_users = || {
+            loads.set(loads.get() + 1);
+            HashMap::from([(uid(1000), "user".to_string())])
+        };
+        // the container uid has no user, but is only looked up once
+        cache_user_names(&mut user_names, [uid(1000), uid(100000), uid(100000)].iter(), load_users);
+        cache_user_names(&mut user_names, [uid(1000), uid(100000)].iter(), load_users);
+        assert_eq!(loads.get(), 1);
+        assert_eq!(user_names, HashMap::from([(uid(1000), Some("user".to_string())), (uid(100000), None)]));
+
+        cache_user_names(&mut user_names, [uid(0)].iter(), load_users);
+        assert_eq!(loads.get(), 2);
+        assert_eq!(user_names.get(&uid(0)), Some(&None));
+    }
+}
@@ -0,0 +1,389 @@
+//! Tracking of power devices through [UPower](https://upower.freedesktop.org/docs/) and of the active
+//! [power-profiles-daemon](https://gitlab.freedesktop.org/upower/power-profiles-daemon) profile,
+//! used for the `EWW_UPOWER` magic variable.
+
+use std::collections::{BTreeMap, HashMap};
+
+use anyhow::{Result, anyhow};
+use futures::StreamExt;
+use simplexpr::dynval::DynVal;
+use yuck::config::script_var_definition::ListenVarSender;
+use zbus::{
+    MatchRule, MessageStream,
+    fdo::PropertiesProxy,
+    names::InterfaceName,
+    zvariant::{ObjectPath, OwnedObjectPath, OwnedValue, Value},
+};
+
+use super::dbus_util::{as_bool, as_f64, as_i64, as_str, unwrap_variant};
+
+const UPOWER_BUS: &str = "org.freedesktop.UPower";
+const UPOWER_PATH: &str = "/org/freedesktop/UPower";
+const UPOWER_INTERFACE: &str = "org.freedesktop.UPower";
+const DEVICE_INTERFACE: &str = "org.freedesktop.UPower.Device";
+
+/// power-profiles-daemon moved to the UPower namespace, but older versions only provide the old `net.hadess` one.
+/// Both are given as (bus name, object path, interface).
+const POWER_PROFILES: &[(&str, &str, &str)] = &[
+    ("org.freedesktop.UPower.PowerProfiles", "/org/freedesktop/UPower/PowerProfiles", "org.freedesktop.UPower.PowerProfiles"),
+    ("net.hadess.PowerProfiles", "/net/hadess/PowerProfiles", "net.hadess.PowerProfiles"),
+];
+
+#[derive(Debug, Default, Clone, PartialEq, serde::Serialize)]
+struct DeviceData {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    vendor: String,
+    model: String,
+    native_path: String,
+    percentage: f64,
+    state: &'static str,
+    /// Times in seconds, `0` if unknown
+    time_to_empty: i64,
+    time_to_full: i64,
+    /// Power draw in W
+    energy_rate: f64,
+    /// Whether the device powers the system, which is false for peripherals
+    power_supply: bool,
+    online: bool,
+    is_present: bool,
+    icon_name: String,
+}
+
+impl DeviceData {
+    /// Apply the given properties of the `org.freedesktop.UPower.Device` interface, as returned by `GetAll`
+    /// or sent in `PropertiesChanged`. Properties that aren't contained are left unchanged.
+    fn update(&mut self, properties: &HashMap<String, OwnedValue>) {
+        let string = |value: &Value| as_str(value).unwrap_or_default().to_string();
+        for (property, value) in properties {
+            match property.as_str() {
+                "Type" => self.kind = device_type(as_i64(value).unwrap_or_default()),
+                "Vendor" => self.vendor = string(value),
+                "Model" => self.model = string(value),
+                "NativePath" => self.native_path = string(value),
+                "Percentage" => self.percentage = as_f64(value).unwrap_or_default(),
+                "State" => self.state = device_state(as_i64(value).unwrap_or_default()),
+                "TimeToEmpty" => self.time_to_empty = as_i64(value).unwrap_or_default(),
+                "TimeToFull" => self.time_to_full = as_i64(value).unwrap_or_default(),
+                "EnergyRate" => self.energy_rate = as_f64(value).unwrap_or_default(),
+                "PowerSupply" => self.power_supply = as_bool(value).unwrap_or_default(),
+                "Online" => self.online = as_bool(value).unwrap_or_default(),
+                "IsPresent" => self.is_present = as_bool(value).unwrap_or_default(),
+                "IconName" => self.icon_name = string(value),
+                _ => {}
+            }
+        }
+    }
+}
+
+fn device_type(kind: i64) -> &'static str {
+    match kind {
+        1 => "line-power",
+        2 => "battery",
+        3 => "ups",
+        4 => "monitor",
+        5 => "mouse",
+        6 => "keyboard",
+        7 => "pda",
+        8 => "phone",
+        9 => "media-player",
+        10 => "tablet",
+        11 => "computer",
+        12 => "gaming-input",
+        13 => "pen",
+        14 => "touchpad",
+        15 => "modem",
+        16 => "network",
+        17 => "headset",
+        18 => "speakers",
+        19 => "headphones",
+        20 => "video",
+        21 => "other-audio",
+        22 => "remote-control",
+        23 => "printer",
+        24 => "scanner",
+        25 => "camera",
+        26 => "wearable",
+        27 => "toy",
+        28 => "bluetooth-generic",
+        _ => "unknown",
+    }
+}
+
+fn device_state(state: i64) -> &'static str {
+    match state {
+        1 => "charging",
+        2 => "discharging",
+        3 => "empty",
+        4 => "fully-charged",
+        5 => "pending-charge",
+        6 => "pending-discharge",
+        _ => "unknown",
+    }
+}
+
+#[derive(Debug, Default, serde::Serialize)]
+struct Data {
+    /// Devices, keyed by the last segment of their object path, i.e. `battery_BAT0`
+    devices: BTreeMap<String, DeviceData>,
+    power_profile: Option<String>,
+    power_profiles: Vec<String>,
+}
+
+impl Data {
+    fn update_power_profiles(&mut self, properties: &HashMap<String, OwnedValue>) {
+        if let Some(profile) = properties.get("ActiveProfile") {
+            self.power_profile = as_str(profile).map(str::to_string);
+        }
+        if let Some(Value::Array(profiles)) = properties.get("Profiles").map(|x| unwrap_variant(x)) {
+            self.power_profiles = profiles
+                .inner()
+                .iter()
+                .filter_map(|profile| match unwrap_variant(profile) {
+                    Value::Dict(profile) => profile.iter().find_map(|(key, value)| match as_str(key) {
+                        Some("Profile") => as_str(value).map(str::to_string),
+                        _ => None,
+                    }),
+                    _ => None,
+                })
+                .collect();
+        }
+    }
+
+    fn to_dynval(&self) -> DynVal {
+        DynVal::from(serde_json::to_string(self).unwrap())
+    }
+}
+
+fn device_name(path: &ObjectPath) -> String {
+    path.as_str().rsplit('/').next().unwrap_or_default().to_string()
+}
+
+pub async fn listen(send: ListenVarSender) -> Result<(), Box<dyn std::error::Error + Sync + Send + 'static>> {
+    let con = zbus::Connection::system().await?;
+    Ok(run(&con, send).await?)
+}
+
+async fn run(con: &zbus::Connection, send: ListenVarSender) -> zbus::Result<()> {
+    // subscribe to changes before reading the initial state, to not miss any devices appearing in between.
+    // The current power-profiles-daemon lives below the UPower path, so only the legacy one needs its own subscription.
+    let mut signals = futures::stream::select_all([
+        MessageStream::for_match_rule(signals_below(UPOWER_PATH)?, con, None).await?,
+        MessageStream::for_match_rule(signals_below(POWER_PROFILES[1].1)?, con, None).await?,
+    ]);
+
+    let mut data = Data::default();
+    let devices: Vec<OwnedObjectPath> = con
+        .call_method(Some(UPOWER_BUS), UPOWER_PATH, Some(UPOWER_INTERFACE), "EnumerateDevices", &())
+        .await?
+        .body()
+        .deserialize()?;
+    for path in devices {
+        add_device(con, &mut data, path.into_inner()).await;
+    }
+    for (bus, path, interface) in POWER_PROFILES {
+        match get_all(con, bus, path, interface).await {
+            Ok(properties) => {
+                data.update_power_profiles(&properties);
+                break;
+            }
+            Err(e) => log::debug!("Failed to read power profiles from {}: {}", bus, e),
+        }
+    }
+    send(data.to_dynval());
+
+    while let Some(msg) = signals.next().await {
+        let msg = msg?;
+        let header = msg.header();
+        let (Some(path), Some(member)) = (header.path(), header.member()) else {
+            continue;
+        };
+        match member.as_str() {
+            "DeviceAdded" => {
+                let device: OwnedObjectPath = msg.body().deserialize()?;
+                add_device(con, &mut data, device.into_inner()).await;
+            }
+            "DeviceRemoved" => {
+                let device: OwnedObjectPath = msg.body().deserialize()?;
+                data.devices.remove(&device_name(&device));
+            }
+            "PropertiesChanged" => {
+                let (interface, changed, _invalidated): (String, HashMap<String, OwnedValue>, Vec<String>) =
+                    msg.body().deserialize()?;
+                if interface == DEVICE_INTERFACE {
+                    if let Some(device) = data.devices.get_mut(&device_name(path)) {
+                        device.update(&changed);
+                    }
+                } else if POWER_PROFILES.iter().any(|(.., profiles_interface)| interface == *profiles_interface) {
+                    data.update_power_profiles(&changed);
+                } else {
+                    continue;
+                }
+            }
+            _ => continue,
+        }
+        send(data.to_dynval());
+    }
+    Ok(())
+}
+
+fn signals_below(path: &'static str) -> zbus::Result<MatchRule<'static>> {
+    Ok(MatchRule::builder().msg_type(zbus::message::Type::Signal).path_namespace(path)?.build())
+}
+
+async fn add_device(con: &zbus::Connection, data: &mut Data, path: ObjectPath<'static>) {
+    match get_all(con, UPOWER_BUS, path.as_str(), DEVICE_INTERFACE).await {
+        Ok(properties) => {
+            let mut device = DeviceData::default();
+            device.update(&properties);
+            data.devices.insert(device_name(&path), device);
+        }
+        Err(e) => log::warn!("Failed to read the state of power device {}: {}", path, e),
+    }
+}
+
+async fn get_all(
+    con: &zbus::Connection,
+    bus: &'static str,
+    path: &str,
+    interface: &'static str,
+) -> zbus::Result<HashMap<String, OwnedValue>> {
+    let properties = PropertiesProxy::builder(con).destination(bus)?.path(path.to_string())?.build().await?;
+    Ok(properties.get_all(InterfaceName::from_static_str_unchecked(interface)).await?)
+}
+
+/// Switch the active power profile of power-profiles-daemon, i.e. to `power-saver`, `balanced` or `performance`.
+pub async fn set_power_profile(profile: &str) -> Result<()> {
+    let con = zbus::Connection::system().await?;
+    set_power_profile_on(&con, profile).await
+}
+
+async fn set_power_profile_on(con: &zbus::Connection, profile: &str) -> Result<()> {
+    let mut errors = Vec::new();
+    for (bus, path, interface) in POWER_PROFILES {
+        let properties = PropertiesProxy::builder(con).destination(*bus)?.path(*path)?.build().await?;
+        match properties.set(InterfaceName::from_static_str_unchecked(interface), "ActiveProfile", Value::from(profile)).await {
+            Ok(()) => return Ok(()),
+            Err(e) => errors.push(format!("{}: {}", bus, e)),
+        }
+    }
+    Err(anyhow!("Failed to set the power profile to `{}`:\n{}", profile, errors.join("\n")))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::private_bus::PrivateBus;
+    use zbus::{interface, object_server::SignalEmitter};
+
+    struct TestUPower;
+
+    #[interface(name = "org.freedesktop.UPower")]
+    impl TestUPower {
+        fn enumerate_devices(&self) -> Vec<ObjectPath<'_>> {
+            vec![ObjectPath::from_static_str_unchecked("/org/freedesktop/UPower/devices/battery_BAT0")]
+        }
+
+        #[zbus(signal)]
+        async fn device_added(ctxt: &SignalEmitter<'_>, device: ObjectPath<'_>) -> zbus::Result<()>;
+    }
+
+    struct TestDevice {
+        kind: u32,
+        percentage: f64,
+    }
+
+    #[interface(name = "org.freedesktop.UPower.Device")]
+    impl TestDevice {
+        #[zbus(property, name = "Type")]
+        fn kind(&self) -> u32 {
+            self.kind
+        }
+
+        #[zbus(property)]
+        fn percentage(&self) -> f64 {
+            self.percentage
+        }
+
+        #[zbus(property)]
+        fn state(&self) -> u32 {
+            2
+        }
+
+        #[zbus(property)]
+        fn time_to_empty(&self) -> i64 {
+            3600
+        }
+    }
+
+    struct TestPowerProfiles {
+        active_profile: String,
+    }
+
+    #[interface(name = "org.freedesktop.UPower.PowerProfiles")]
+    impl TestPowerProfiles {
+        #[zbus(property)]
+        fn active_profile(&self) -> String {
+            self.active_profile.clone()
+        }
+
+        #[zbus(property)]
+        fn set_active_profile(&mut self, profile: String) {
+            self.active_profile = profile;
+        }
+
+        #[zbus(property)]
+        fn profiles(&self) -> Vec<HashMap<String, OwnedValue>> {
+            ["power-saver", "balanced"]
+                .into_iter()
+                .map(|profile| HashMap::from([("Profile".to_string(), Value::from(profile).try_into().unwrap())]))
+                .collect()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_upower() {
+        let Some(bus) = PrivateBus::start() else {
+            return;
+        };
+        let upower_con = bus.connection().await;
+        let server = upower_con.object_server();
+        server.at(UPOWER_PATH, TestUPower).await.unwrap();
+        server.at("/org/freedesktop/UPower/devices/battery_BAT0", TestDevice { kind: 2, percentage: 50.0 }).await.unwrap();
+        server
+            .at("/org/freedesktop/UPower/PowerProfiles", TestPowerProfiles { active_profile: "balanced".to_string() })
+            .await
+            .unwrap();
+        upower_con.request_name(UPOWER_BUS).await.unwrap();
+        upower_con.request_name("org.freedesktop.UPower.PowerProfiles").await.unwrap();
+
+        let (send, mut recv) = tokio::sync::mpsc::unbounded_channel();
+        let eww_con = bus.connection().await;
+        tokio::spawn(async move { run(&eww_con, Box::new(move |value| send.send(value).unwrap())).await });
+        let mut next_value =
+            async || serde_json::from_str::<serde_json::Value>(&recv.recv().await.unwrap().as_string().unwrap()).unwrap();
+
+        let value = next_value().await;
+        assert_eq!(value["devices"]["battery_BAT0"]["type"], "battery");
+        assert_eq!(value["devices"]["battery_BAT0"]["percentage"], 50.0);
+        assert_eq!(value["devices"]["battery_BAT0"]["state"], "discharging");
+        assert_eq!(value["devices"]["battery_BAT0"]["time_to_empty"], 3600);
+        assert_eq!(value["power_profile"], "balanced");
+        assert_eq!(value["power_profiles"], serde_json::json!(["power-saver", "balanced"]));
+
+        let device = server.interface::<_, TestDevice>("/org/freedesktop/UPower/devices/battery_BAT0").await.unwrap();
+        device.get_mut().await.percentage = 49.0;
+        device.get().await.percentage_changed(device.signal_emitter()).await.unwrap();
+        assert_eq!(next_value().await["devices"]["battery_BAT0"]["percentage"], 49.0);
+
+        server.at("/org/freedesktop/UPower/devices/mouse_0", TestDevice { kind: 5, percentage: 80.0 }).await.unwrap();
+        let emitter = SignalEmitter::new(&upower_con, UPOWER_PATH).unwrap();
+        TestUPower::device_added(&emitter, ObjectPath::from_static_str_unchecked("/org/freedesktop/UPower/devices/mouse_0"))
+            .await
+            .unwrap();
+        assert_eq!(next_value().await["devices"]["mouse_0"]["type"], "mouse");
+
+        set_power_profile_on(&bus.connection().await, "power-saver").await.unwrap();
+        assert_eq!(next_value().await["power_profile"], "power-saver");
+    }
+}
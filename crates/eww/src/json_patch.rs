@@ -0,0 +1,163 @@
+//! Updating JSON values in place, either by deep merging another value into them,
+//! or by applying a JSON patch as specified in [RFC 6902](https://datatracker.ietf.org/doc/html/rfc6902).
+
+use anyhow::{Context, Result, bail};
+use serde_json::Value;
+
+/// Deep merge `patch` into `target`: objects are merged key by key, any other value replaces the previous one.
+/// Like in a JSON merge patch, a `null` removes the key from the object it is in.
+pub fn merge(target: &mut Value, patch: Value) {
+    let Value::Object(patch) = patch else {
+        *target = patch;
+        return;
+    };
+    if !target.is_object() {
+        *target = Value::Object(serde_json::Map::new());
+    }
+    let Value::Object(target) = target else { unreachable!() };
+    for (key, value) in patch {
+        if value.is_null() {
+            target.remove(&key);
+        } else {
+            merge(target.entry(key).or_insert(Value::Null), value);
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+enum Operation {
+    Add { path: String, value: Value },
+    Remove { path: String },
+    Replace { path: String, value: Value },
+    Move { from: String, path: String },
+    Copy { from: String, path: String },
+    Test { path: String, value: Value },
+}
+
+/// Apply a JSON patch, given as an array of operations. If any of the operations fails, `target` is left unchanged.
+pub fn apply_patch(target: &mut Value, patch: Value) -> Result<()> {
+    let operations: Vec<Operation> = serde_json::from_value(patch).context("Invalid JSON patch")?;
+    let mut patched = target.clone();
+    for operation in operations {
+        apply_operation(&mut patched, operation)?;
+    }
+    *target = patched;
+    Ok(())
+}
+
+fn apply_operation(target: &mut Value, operation: Operation) -> Result<()> {
+    match operation {
+        Operation::Add { path, value } => add(target, &path, value),
+        Operation::Remove { path } => remove(target, &path).map(|_| ()),
+        Operation::Replace { path, value } => {
+            *get_mut(target, &path)? = value;
+            Ok(())
+        }
+        Operation::Move { from, path } => {
+            if path.starts_with(&format!("{}/", from)) {
+                bail!("Can't move `{}` into one of its children", from);
+            }
+            let value = remove(target, &from)?;
+            add(target, &path, value)
+        }
+        Operation::Copy { from, path } => {
+            let value = get_mut(target, &from)?.clone();
+            add(target, &path, value)
+        }
+        Operation::Test { path, value } => {
+            if *get_mut(target, &path)? != value {
+                bail!("Test failed, `{}` is not {}", path, value);
+            }
+            Ok(())
+        }
+    }
+}
+
+fn add(target: &mut Value, path: &str, value: Value) -> Result<()> {
+    if path.is_empty() {
+        *target = value;
+        return Ok(());
+    }
+    let (parent, key) = split_pointer(path)?;
+    match get_mut(target, parent)? {
+        Value::Object(object) => {
+            object.insert(key, value);
+        }
+        Value::Array(array) => {
+            let index = if key == "-" {
+                array.len()
+            } else {
+                key.parse().ok().filter(|index| *index <= array.len()).with_context(|| format!("Invalid index in `{}`", path))?
+            };
+            array.insert(index, value);
+        }
+        _ => bail!("Can't add to `{}`, as it is neither an object nor an array", parent),
+    }
+    Ok(())
+}
+
+fn remove(target: &mut Value, path: &str) -> Result<Value> {
+    let (parent, key) = split_pointer(path)?;
+    match get_mut(target, parent)? {
+        Value::Object(object) => object.remove(&key).with_context(|| format!("Nothing to remove at `{}`", path)),
+        Value::Array(array) => {
+            let index: usize =
+                key.parse().ok().filter(|index| *index < array.len()).with_context(|| format!("Invalid index in `{}`", path))?;
+            Ok(array.remove(index))
+        }
+        _ => bail!("Can't remove from `{}`, as it is neither an object nor an array", parent),
+    }
+}
+
+fn get_mut<'a>(target: &'a mut Value, path: &str) -> Result<&'a mut Value> {
+    target.pointer_mut(path).with_context(|| format!("Nothing at `{}`", path))
+}
+
+/// Split a JSON pointer into the pointer to its parent, and its unescaped last reference token.
+fn split_pointer(path: &str) -> Result<(&str, String)> {
+    let (parent, key) = path.rsplit_once('/').with_context(|| format!("`{}` is not a valid JSON pointer", path))?;
+    Ok((parent, key.replace("~1", "/").replace("~0", "~")))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_merge() {
+        let mut value = json!({"a": {"b": 1, "c": [1, 2]}, "d": "x"});
+        merge(&mut value, json!({"a": {"b": 2, "c": [3]}, "d": null, "e": {"f": null, "g": true}}));
+        assert_eq!(value, json!({"a": {"b": 2, "c": [3]}, "e": {"g": true}}));
+        merge(&mut value, json!(5));
+        assert_eq!(value, json!(5));
+    }
+
+    #[test]
+    fn test_apply_patch() {
+        let mut value = json!({"workspaces": [{"id": 1}, {"id": 3}], "a/b": 0});
+        apply_patch(
+            &mut value,
+            json!([
+                {"op": "add", "path": "/workspaces/1", "value": {"id": 2}},
+                {"op": "add", "path": "/workspaces/-", "value": {"id": 4}},
+                {"op": "replace", "path": "/workspaces/0/id", "value": 0},
+                {"op": "remove", "path": "/a~1b"},
+                {"op": "copy", "from": "/workspaces/3", "path": "/last"},
+                {"op": "move", "from": "/workspaces/2", "path": "/removed"},
+                {"op": "test", "path": "/last/id", "value": 4},
+            ]),
+        )
+        .unwrap();
+        assert_eq!(value, json!({"workspaces": [{"id": 0}, {"id": 2}, {"id": 4}], "last": {"id": 4}, "removed": {"id": 3}}));
+
+        let before = value.clone();
+        let failing = json!([{"op": "remove", "path": "/last"}, {"op": "test", "path": "/last/id", "value": 4}]);
+        assert!(apply_patch(&mut value, failing).is_err());
+        assert_eq!(value, before);
+        assert!(apply_patch(&mut value, json!([{"op": "remove", "path": "/workspaces/3"}])).is_err());
+        assert!(apply_patch(&mut value, json!([{"op": "move", "from": "/last", "path": "/last/x"}])).is_err());
+        assert!(apply_patch(&mut value, json!({"op": "add"})).is_err());
+    }
+}
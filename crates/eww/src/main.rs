@@ -22,11 +22,15 @@ mod display_backend;
 mod error_handling_ctx;
 mod file_database;
 mod ipc_server;
+mod json_patch;
+mod notification_server;
 mod opts;
 mod paths;
 mod script_var_handler;
 mod server;
 mod state;
+#[cfg(test)]
+mod test_util;
 mod util;
 mod widgets;
 mod window_arguments;
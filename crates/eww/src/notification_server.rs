@@ -0,0 +1,95 @@
+//! Management of the optional notification server (see [`notifier_host::NotificationServer`]),
+//! which publishes the notifications it receives in the `EWW_NOTIFICATIONS` variable.
+
+use anyhow::{Context, Result, bail};
+use eww_shared_util::VarName;
+use notifier_host::{Notification, NotificationServerHandle, Urgency};
+use once_cell::sync::Lazy;
+use simplexpr::dynval::DynVal;
+use tokio::sync::mpsc::UnboundedSender;
+use yuck::config::notification_server_definition::NotificationServerDefinition;
+
+use crate::{app::DaemonCommand, config::inbuilt::NOTIFICATIONS_VAR_NAME};
+
+static SERVER: Lazy<tokio::sync::Mutex<Option<NotificationServerHandle>>> = Lazy::new(|| tokio::sync::Mutex::new(None));
+
+/// Start, reconfigure or stop the notification server, depending on whether it is defined in the configuration.
+/// If it is already running, the current notifications are published again, as loading a configuration resets all variables.
+pub fn configure(definition: Option<NotificationServerDefinition>, evt_send: UnboundedSender<DaemonCommand>) {
+    tokio::spawn(async move {
+        let mut server = SERVER.lock().await;
+        match (definition, server.as_ref()) {
+            (Some(definition), Some(handle)) => handle.set_history_size(definition.history_size),
+            (Some(definition), None) => match start(definition.history_size, evt_send).await {
+                Ok(handle) => *server = Some(handle),
+                Err(e) => log::error!("Failed to start the notification server: {:?}", e),
+            },
+            (None, Some(_)) => {
+                if let Some(handle) = server.take() {
+                    crate::print_result_err!("while stopping the notification server", handle.stop().await);
+                }
+            }
+            (None, None) => {}
+        }
+    });
+}
+
+async fn start(history_size: usize, evt_send: UnboundedSender<DaemonCommand>) -> Result<NotificationServerHandle> {
+    let con = zbus::Connection::session().await?;
+    let server = notifier_host::NotificationServer::new(history_size, move |notifications| {
+        let update =
+            DaemonCommand::UpdateVars(vec![(VarName::from(NOTIFICATIONS_VAR_NAME), notifications_to_dynval(notifications))]);
+        crate::print_result_err!("while sending notification update", evt_send.send(update));
+    });
+    Ok(server.attach_to(&con).await?)
+}
+
+/// Dismiss the given notifications, or all of them.
+pub async fn dismiss(ids: Vec<u32>, all: bool) -> Result<()> {
+    let server = SERVER.lock().await;
+    let handle = server.as_ref().context("The notification server is not enabled, add `(defnotifications)` to your config")?;
+    if all {
+        handle.dismiss_all().await?;
+    }
+    for id in ids {
+        if !handle.dismiss(id).await? {
+            bail!("There is no notification with the id {}", id);
+        }
+    }
+    Ok(())
+}
+
+/// Invoke an action of a notification, notifying the application that sent it.
+pub async fn invoke_action(id: u32, action: &str) -> Result<()> {
+    let server = SERVER.lock().await;
+    let handle = server.as_ref().context("The notification server is not enabled, add `(defnotifications)` to your config")?;
+    if !handle.invoke_action(id, action).await? {
+        bail!("There is no notification with the id {} that has the action `{}`", id, action);
+    }
+    Ok(())
+}
+
+fn notifications_to_dynval(notifications: &[Notification]) -> DynVal {
+    let notifications: Vec<_> = notifications
+        .iter()
+        .map(|notification| {
+            serde_json::json!({
+                "id": notification.id,
+                "app_name": notification.app_name,
+                "app_icon": notification.app_icon,
+                "summary": notification.summary,
+                "body": notification.body,
+                "actions": notification.actions.iter().map(|(key, label)| serde_json::json!({ "key": key, "label": label })).collect::<Vec<_>>(),
+                "urgency": match notification.urgency {
+                    Urgency::Low => "low",
+                    Urgency::Normal => "normal",
+                    Urgency::Critical => "critical",
+                },
+                "expire_timeout": notification.expire_timeout,
+                "timestamp": notification.timestamp,
+                "expired": notification.expired,
+            })
+        })
+        .collect();
+    DynVal::from(serde_json::Value::from(notifications).to_string())
+}
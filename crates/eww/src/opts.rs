@@ -4,7 +4,7 @@ use eww_shared_util::VarName;
 use serde::{Deserialize, Serialize};
 use simplexpr::dynval::DynVal;
 use yuck::{
-    config::{monitor::MonitorIdentifier, window_geometry::AnchorPoint},
+    config::{monitor::MonitorIdentifier, script_var_definition::DbusBus, window_geometry::AnchorPoint},
     value::Coords,
 };
 
@@ -199,6 +199,44 @@ pub enum ActionWithServer {
     /// Print out the scope graph structure in graphviz dot format.
     #[command(name = "graph")]
     ShowGraph,
+
+    /// Dismiss notifications received by the notification server, removing them from `EWW_NOTIFICATIONS`
+    #[command(name = "dismiss-notifications")]
+    DismissNotifications {
+        /// Ids of the notifications to dismiss
+        ids: Vec<u32>,
+
+        /// Dismiss all notifications
+        #[arg(long)]
+        all: bool,
+    },
+
+    /// Invoke an action of a notification received by the notification server, i.e.: `eww invoke-notification-action 3 default`
+    #[command(name = "invoke-notification-action")]
+    InvokeNotificationAction { id: u32, action: String },
+
+    /// Switch the active profile of power-profiles-daemon, i.e.: `eww set-power-profile power-saver`
+    #[command(name = "set-power-profile")]
+    SetPowerProfile { profile: String },
+
+    /// Call a D-Bus method and print its reply as JSON, i.e.: `eww call-dbus org.freedesktop.UPower /org/freedesktop/UPower org.freedesktop.UPower EnumerateDevices`
+    #[command(name = "call-dbus")]
+    CallDbus {
+        /// Use the system bus instead of the session bus
+        #[arg(long)]
+        system: bool,
+
+        /// D-Bus signature of the arguments, i.e. `su`. Only basic types are supported.
+        /// If not given, the types are guessed from the arguments.
+        #[arg(long)]
+        signature: Option<String>,
+
+        destination: String,
+        path: String,
+        interface: String,
+        method: String,
+        args: Vec<String>,
+    },
 }
 
 impl Opt {
@@ -289,6 +327,28 @@ impl ActionWithServer {
             }
             ActionWithServer::ShowDebug => return with_response_channel(app::DaemonCommand::PrintDebug),
             ActionWithServer::ShowGraph => return with_response_channel(app::DaemonCommand::PrintGraph),
+            ActionWithServer::DismissNotifications { ids, all } => {
+                return with_response_channel(|sender| app::DaemonCommand::DismissNotifications { ids, all, sender });
+            }
+            ActionWithServer::InvokeNotificationAction { id, action } => {
+                return with_response_channel(|sender| app::DaemonCommand::InvokeNotificationAction { id, action, sender });
+            }
+            ActionWithServer::SetPowerProfile { profile } => {
+                return with_response_channel(|sender| app::DaemonCommand::SetPowerProfile { profile, sender });
+            }
+            ActionWithServer::CallDbus { system, signature, destination, path, interface, method, args } => {
+                let bus = if system { DbusBus::System } else { DbusBus::Session };
+                return with_response_channel(|sender| app::DaemonCommand::CallDbus {
+                    bus,
+                    destination,
+                    path,
+                    interface,
+                    method,
+                    signature,
+                    args,
+                    sender,
+                });
+            }
         };
         (command, None)
     }
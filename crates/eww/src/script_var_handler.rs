@@ -2,9 +2,13 @@ use std::collections::HashMap;
 
 use crate::{
     app,
-    config::{create_script_var_failed_warn, script_var},
+    config::{
+        create_script_var_failed_warn, dbus_var,
+        script_var::{self, ListenVarStatus},
+    },
+    json_patch,
 };
-use anyhow::{Result, anyhow};
+use anyhow::{Context, Result, anyhow};
 use app::DaemonCommand;
 
 use eww_shared_util::VarName;
@@ -18,7 +22,10 @@ use tokio::{
     sync::mpsc::UnboundedSender,
 };
 use tokio_util::sync::CancellationToken;
-use yuck::config::script_var_definition::{ListenScriptVar, PollScriptVar, ScriptVarDefinition, VarSource};
+use yuck::config::script_var_definition::{
+    DbusVar, FileVar, ListenScriptVar, ListenVarMode, ListenVarSource, PollErrorBehavior, PollScriptVar, RestartPolicy,
+    ScriptVarDefinition, VarSource,
+};
 
 /// Initialize the script var handler, and return a handle to that handler, which can be used to control
 /// the script var execution.
@@ -36,7 +43,9 @@ pub fn init(evt_send: UnboundedSender<DaemonCommand>) -> ScriptVarHandlerHandle
                 let _: Result<_> = async {
                     let mut handler = ScriptVarHandler {
                         listen_handler: ListenVarHandler::new(evt_send.clone())?,
-                        poll_handler: PollVarHandler::new(evt_send)?,
+                        poll_handler: PollVarHandler::new(evt_send.clone())?,
+                        file_handler: FileVarHandler::new(evt_send.clone())?,
+                        dbus_handler: DbusVarHandler::new(evt_send)?,
                     };
                     crate::loop_select_exiting! {
                         Some(msg) = msg_recv.recv() => match msg {
@@ -113,6 +122,8 @@ enum ScriptVarHandlerMsg {
 struct ScriptVarHandler {
     listen_handler: ListenVarHandler,
     poll_handler: PollVarHandler,
+    file_handler: FileVarHandler,
+    dbus_handler: DbusVarHandler,
 }
 
 impl ScriptVarHandler {
@@ -120,6 +131,8 @@ impl ScriptVarHandler {
         match script_var {
             ScriptVarDefinition::Poll(var) => self.poll_handler.start(var).await,
             ScriptVarDefinition::Listen(var) => self.listen_handler.start(var).await,
+            ScriptVarDefinition::File(var) => self.file_handler.start(var),
+            ScriptVarDefinition::Dbus(var) => self.dbus_handler.start(var),
         };
     }
 
@@ -128,6 +141,8 @@ impl ScriptVarHandler {
         log::debug!("Stopping script var process for variable {}", name);
         self.listen_handler.stop_for_variable(name).await;
         self.poll_handler.stop_for_variable(name);
+        self.file_handler.stop_for_variable(name);
+        self.dbus_handler.stop_for_variable(name);
         Ok(())
     }
 
@@ -136,6 +151,8 @@ impl ScriptVarHandler {
         log::debug!("Stopping script-var-handlers");
         self.listen_handler.stop_all().await;
         self.poll_handler.stop_all();
+        self.file_handler.stop_all();
+        self.dbus_handler.stop_all();
     }
 }
 
@@ -160,23 +177,16 @@ impl PollVarHandler {
         self.poll_handles.insert(var.name.clone(), cancellation_token.clone());
         let evt_send = self.evt_send.clone();
         tokio::spawn(async move {
-            let result: Result<_> = (|| {
-                evt_send.send(app::DaemonCommand::UpdateVars(vec![(var.name.clone(), run_poll_once(&var)?)]))?;
-                Ok(())
-            })();
-            if let Err(err) = result {
+            // the error of a previous run of this variable may still be set
+            let mut failing = true;
+            if let Err(err) = poll_once(&var, &evt_send, &mut failing).await {
                 crate::error_handling_ctx::print_error(err);
             }
 
             crate::loop_select_exiting! {
                 _ = cancellation_token.cancelled() => break,
                 _ = tokio::time::sleep(var.interval) => {
-                    let result: Result<_> = (|| {
-                        evt_send.send(app::DaemonCommand::UpdateVars(vec![(var.name.clone(), run_poll_once(&var)?)]))?;
-                        Ok(())
-                    })();
-
-                    if let Err(err) = result {
+                    if let Err(err) = poll_once(&var, &evt_send, &mut failing).await {
                         crate::error_handling_ctx::print_error(err);
                     }
                 }
@@ -196,12 +206,59 @@ impl PollVarHandler {
     }
 }
 
-fn run_poll_once(var: &PollScriptVar) -> Result<DynVal> {
-    match &var.command {
-        VarSource::Shell(span, command) => {
-            script_var::run_command(command).map_err(|e| anyhow!(create_script_var_failed_warn(*span, &var.name, &e.to_string())))
+/// Poll a variable once, and send its new value along with its error variable.
+/// `failing` tracks whether the last poll failed, so the error variable is only reset when the variable recovers.
+/// If polling fails, the value of the variable is changed according to its `:on-error`.
+async fn poll_once(var: &PollScriptVar, evt_send: &UnboundedSender<DaemonCommand>, failing: &mut bool) -> Result<()> {
+    let error_var_name = var.error_var_name();
+    let updates = match run_poll_once(var).await {
+        Ok(value) => {
+            let mut updates = vec![(var.name.clone(), value)];
+            if std::mem::take(failing) {
+                updates.extend(error_var_name.map(|name| (name, DynVal::from(""))));
+            }
+            updates
+        }
+        Err(err) => {
+            *failing = true;
+            let mut updates: Vec<_> = error_var_name.map(|name| (name, DynVal::from(err.to_string()))).into_iter().collect();
+            if var.on_error == PollErrorBehavior::Initial {
+                updates.extend(var.initial_value.clone().map(|value| (var.name.clone(), value)));
+            }
+            if !updates.is_empty() {
+                evt_send.send(DaemonCommand::UpdateVars(updates))?;
+            }
+            // the error value may reference other variables, so it has to be evaluated by the app
+            if let (PollErrorBehavior::Value, Some(error_value)) = (var.on_error, &var.error_value) {
+                evt_send.send(DaemonCommand::UpdateVarFromExpr(var.name.clone(), error_value.clone()))?;
+            }
+            return Err(match &var.command {
+                VarSource::Shell(span, _) => anyhow!(create_script_var_failed_warn(*span, &var.name, &err.to_string())),
+                _ => err,
+            });
+        }
+    };
+    evt_send.send(DaemonCommand::UpdateVars(updates))?;
+    Ok(())
+}
+
+async fn run_poll_once(var: &PollScriptVar) -> Result<DynVal> {
+    let run = async {
+        match &var.command {
+            VarSource::Shell(_, command) => script_var::run_command_async(command).await,
+            VarSource::Expression(expr) => {
+                // scripts block on file I/O, so they must not run on the runtime itself
+                let (expr, allowed_paths) = (expr.clone(), var.allowed_paths.clone());
+                tokio::task::spawn_blocking(move || script_var::run_expression(&expr, &allowed_paths)).await?
+            }
+            VarSource::Function(x) => x().map_err(|e| anyhow!(e)),
+        }
+    };
+    match var.timeout {
+        Some(timeout) => {
+            tokio::time::timeout(timeout, run).await.unwrap_or_else(|_| Err(anyhow!("Timed out after {:?}", timeout)))
         }
-        VarSource::Function(x) => x().map_err(|e| anyhow!(e)),
+        None => run.await,
     }
 }
 
@@ -211,6 +268,156 @@ impl Drop for PollVarHandler {
     }
 }
 
+struct FileVarHandler {
+    evt_send: UnboundedSender<DaemonCommand>,
+    watch_handles: HashMap<VarName, CancellationToken>,
+}
+
+impl FileVarHandler {
+    fn new(evt_send: UnboundedSender<DaemonCommand>) -> Result<Self> {
+        let handler = FileVarHandler { evt_send, watch_handles: HashMap::new() };
+        Ok(handler)
+    }
+
+    fn start(&mut self, var: FileVar) {
+        if self.watch_handles.contains_key(&var.name) {
+            return;
+        }
+
+        log::debug!("starting file var {}", &var.name);
+        let cancellation_token = CancellationToken::new();
+        self.watch_handles.insert(var.name.clone(), cancellation_token.clone());
+        let evt_send = self.evt_send.clone();
+        tokio::spawn(async move {
+            if let Err(err) = watch_file_var(&var, &evt_send, cancellation_token).await {
+                crate::error_handling_ctx::print_error(err);
+            }
+        });
+    }
+
+    fn stop_for_variable(&mut self, name: &VarName) {
+        if let Some(token) = self.watch_handles.remove(name) {
+            log::debug!("stopped file var {}", name);
+            token.cancel()
+        }
+    }
+
+    fn stop_all(&mut self) {
+        self.watch_handles.drain().for_each(|(_, token)| token.cancel());
+    }
+}
+
+impl Drop for FileVarHandler {
+    fn drop(&mut self) {
+        self.stop_all();
+    }
+}
+
+/// Send the value of a file var whenever its file changes, until the `cancellation_token` is cancelled.
+async fn watch_file_var(
+    var: &FileVar,
+    evt_send: &UnboundedSender<DaemonCommand>,
+    cancellation_token: CancellationToken,
+) -> Result<()> {
+    use notify::{RecursiveMode, Watcher};
+
+    let path = script_var::resolve_file_var_path(&var.path)?;
+    let (Some(dir), Some(file_name)) = (path.parent(), path.file_name()) else {
+        anyhow::bail!("Invalid path for file var {}: {}", var.name, path.display());
+    };
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let watched_file_name = file_name.to_owned();
+    let mut watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| match res {
+        Ok(event) if !event.kind.is_access() && event.paths.iter().any(|path| path.file_name() == Some(&watched_file_name)) => {
+            let _ = tx.send(());
+        }
+        Ok(_) => {}
+        Err(e) => log::error!("Encountered error while watching file: {}", e),
+    })?;
+    // watching the directory instead of the file itself also notices the file being created, or replaced atomically
+    watcher
+        .watch(if dir.as_os_str().is_empty() { std::path::Path::new(".") } else { dir }, RecursiveMode::NonRecursive)
+        .with_context(|| format!("Failed to watch {} for file var {}", dir.display(), var.name))?;
+
+    // the file may have changed since the initial value was read
+    let mut last_value = None;
+    let mut update = || {
+        match script_var::read_file_var(var, &path) {
+            Ok(value) if last_value.as_ref() != Some(&value) => {
+                last_value = Some(value.clone());
+                evt_send.send(DaemonCommand::UpdateVars(vec![(var.name.clone(), value)]))?;
+            }
+            Ok(_) => {}
+            // the file is briefly missing while it's being replaced, which is not worth a warning
+            Err(err) if !path.exists() => log::debug!("Failed to read file of {}: {:?}", var.name, err),
+            Err(err) => log::warn!("Failed to read file of {}: {:?}", var.name, err),
+        }
+        Ok::<_, anyhow::Error>(())
+    };
+    update()?;
+
+    crate::loop_select_exiting! {
+        _ = cancellation_token.cancelled() => break,
+        Some(()) = rx.recv() => update()?,
+        else => break,
+    };
+    Ok(())
+}
+
+struct DbusVarHandler {
+    evt_send: UnboundedSender<DaemonCommand>,
+    dbus_handles: HashMap<VarName, CancellationToken>,
+}
+
+impl DbusVarHandler {
+    fn new(evt_send: UnboundedSender<DaemonCommand>) -> Result<Self> {
+        let handler = DbusVarHandler { evt_send, dbus_handles: HashMap::new() };
+        Ok(handler)
+    }
+
+    fn start(&mut self, var: DbusVar) {
+        if self.dbus_handles.contains_key(&var.name) {
+            return;
+        }
+
+        log::debug!("starting dbus var {}", &var.name);
+        let cancellation_token = CancellationToken::new();
+        self.dbus_handles.insert(var.name.clone(), cancellation_token.clone());
+        let evt_send = self.evt_send.clone();
+        tokio::spawn(async move {
+            let send = |new_value| {
+                crate::print_result_err!(
+                    "while sending dbus-var update",
+                    evt_send.send(DaemonCommand::UpdateVars(vec![(var.name.clone(), new_value)]))
+                );
+            };
+            tokio::select! {
+                result = dbus_var::run(&var, send) => if let Err(err) = result {
+                    log::error!("Error while running dbus-var {}: {:?}", var.name, err);
+                },
+                _ = cancellation_token.cancelled() => {},
+            }
+        });
+    }
+
+    fn stop_for_variable(&mut self, name: &VarName) {
+        if let Some(token) = self.dbus_handles.remove(name) {
+            log::debug!("stopped dbus var {}", name);
+            token.cancel()
+        }
+    }
+
+    fn stop_all(&mut self) {
+        self.dbus_handles.drain().for_each(|(_, token)| token.cancel());
+    }
+}
+
+impl Drop for DbusVarHandler {
+    fn drop(&mut self) {
+        self.stop_all();
+    }
+}
+
 struct ListenVarHandler {
     evt_send: UnboundedSender<DaemonCommand>,
     listen_process_handles: HashMap<VarName, cancellation::AwaitableCancelationSender>,
@@ -236,73 +443,220 @@ impl ListenVarHandler {
         self.listen_process_handles.insert(var.name.clone(), cancel_send);
 
         let evt_send = self.evt_send.clone();
-        tokio::spawn(async move {
-            let result: Result<_> = async {
-                let mut handle = unsafe {
-                    tokio::process::Command::new("sh")
-                        .args(["-c", &var.command])
-                        .stdout(std::process::Stdio::piped())
-                        .stderr(std::process::Stdio::piped())
-                        .stdin(std::process::Stdio::null())
-                        .pre_exec(|| {
-                            let _ = setpgid(Pid::from_raw(0), Pid::from_raw(0));
-                            Ok(())
-                        })
-                        .spawn()?
-                };
-                let mut stdout_lines = BufReader::new(handle.stdout.take().unwrap()).lines();
-                let mut stderr_lines = BufReader::new(handle.stderr.take().unwrap()).lines();
-                let mut completion_notify = None;
-                crate::loop_select_exiting! {
-                    _ = handle.wait() => break,
-                    notify = cancel_recv.wait_for_cancel() => {
-                        completion_notify = notify;
-                        break;
-                    }
-                    Ok(Some(line)) = stdout_lines.next_line() => {
-                        let new_value = DynVal::from_string(line.to_owned());
-                        evt_send.send(DaemonCommand::UpdateVars(vec![(var.name.to_owned(), new_value)]))?;
-                    }
-                    Ok(Some(line)) = stderr_lines.next_line() => {
-                        log::warn!("stderr of `{}`: {}", var.name, line);
+        let command = match &var.command {
+            ListenVarSource::Shell(_, command) => command.clone(),
+            ListenVarSource::Function(f) => {
+                let name = var.name.clone();
+                let future = f(Box::new(move |new_value| {
+                    crate::print_result_err!(
+                        "while sending listen-var update",
+                        evt_send.send(DaemonCommand::UpdateVars(vec![(name.clone(), new_value)]))
+                    );
+                }));
+                tokio::spawn(async move {
+                    tokio::select! {
+                        result = future => if let Err(err) = result {
+                            log::error!("Error while running listen-var {}: {:?}", var.name, err);
+                        },
+                        Some(completion_notify) = cancel_recv.wait_for_cancel() => completion_notify.completed().await,
                     }
-                    else => break,
-                };
-                terminate_handle(handle).await;
+                });
+                return;
+            }
+        };
+        tokio::spawn(run_listen_script(var, command, evt_send, cancel_recv));
+    }
+
+    async fn stop_for_variable(&mut self, name: &VarName) {
+        if let Some(token) = self.listen_process_handles.remove(name) {
+            log::debug!("stopped listen-var {}", name);
+            token.cancel().await;
+        }
+    }
+
+    async fn stop_all(&mut self) {
+        for (_, token) in self.listen_process_handles.drain() {
+            token.cancel().await;
+        }
+    }
+}
+
+/// Upper limit for the exponentially growing delay between restarts of a listen-var script.
+const MAX_RESTART_DELAY: std::time::Duration = std::time::Duration::from_secs(300);
+/// A listen-var script that ran for at least this long is not considered to be failing repeatedly,
+/// so the delay before its next restart starts over.
+const RESTART_BACKOFF_RESET: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// How a single run of a listen-var script ended.
+enum ListenScriptExit {
+    /// The variable was stopped, or eww is exiting.
+    Cancelled(Option<cancellation::CancelCompletionNotifier>),
+    Exited(std::process::ExitStatus),
+}
 
+/// Run the script of a listen-var, restarting it according to the variables restart policy, until the variable is stopped.
+/// The status of the script is published in the variables status variable.
+async fn run_listen_script(
+    var: ListenScriptVar,
+    command: String,
+    evt_send: UnboundedSender<DaemonCommand>,
+    mut cancel_recv: cancellation::AwaitableCancelationReceiver,
+) {
+    let status_var_name = var.status_var_name();
+    let send_status = |status: ListenVarStatus| {
+        crate::print_result_err!(
+            "while sending listen-var status",
+            evt_send.send(DaemonCommand::UpdateVars(vec![(status_var_name.clone(), status.to_dynval())]))
+        );
+    };
+    let mut status = ListenVarStatus::default();
+    // kept across restarts, so that a restarted script can continue to send updates
+    let mut value = match ListenVarValue::new(&var) {
+        Ok(value) => value,
+        Err(err) => {
+            crate::error_handling_ctx::print_error(err);
+            return;
+        }
+    };
+    let mut consecutive_restarts = 0;
+    loop {
+        status.running = true;
+        status.exit_code = None;
+        send_status(status);
+        let started_at = std::time::Instant::now();
+        let exit_status = match run_listen_script_once(&var, &command, &mut value, &evt_send, &mut cancel_recv).await {
+            Ok(ListenScriptExit::Cancelled(completion_notify)) => {
                 if let Some(completion_notify) = completion_notify {
                     completion_notify.completed().await;
                 }
-                Ok(())
+                return;
             }
-            .await;
-
-            if let Err(err) = result {
+            Ok(ListenScriptExit::Exited(exit_status)) => Some(exit_status),
+            Err(err) => {
                 log::error!(
                     "[{}:{}] Error while executing listen-var command {}: {:?}",
                     ::std::file!(),
                     ::std::line!(),
-                    &var.command,
+                    &command,
                     err
                 );
+                None
             }
-        });
+        };
+        status.running = false;
+        status.exit_code = exit_status.and_then(|exit_status| exit_status.code());
+        send_status(status);
+
+        let failed = !exit_status.is_some_and(|exit_status| exit_status.success());
+        let restart = match var.restart {
+            RestartPolicy::Always => true,
+            RestartPolicy::OnFailure => failed,
+            RestartPolicy::Never => false,
+        };
+        if !restart {
+            return;
+        }
+        if started_at.elapsed() >= RESTART_BACKOFF_RESET {
+            consecutive_restarts = 0;
+        }
+        if var.max_retries.is_some_and(|max_retries| consecutive_restarts >= max_retries) {
+            log::warn!(
+                "The script of listen-var {} exited {} times in a row, not restarting it again",
+                var.name,
+                consecutive_restarts + 1
+            );
+            return;
+        }
+        let delay = var.restart_delay.saturating_mul(2_u32.saturating_pow(consecutive_restarts)).min(MAX_RESTART_DELAY);
+        log::info!("The script of listen-var {} exited, restarting it in {:?}", var.name, delay);
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {}
+            completion_notify = cancel_recv.wait_for_cancel() => {
+                if let Some(completion_notify) = completion_notify {
+                    completion_notify.completed().await;
+                }
+                return;
+            }
+        }
+        consecutive_restarts += 1;
+        status.restarts += 1;
     }
+}
 
-    async fn stop_for_variable(&mut self, name: &VarName) {
-        if let Some(token) = self.listen_process_handles.remove(name) {
-            log::debug!("stopped listen-var {}", name);
-            token.cancel().await;
-        }
+/// The current value of a listen-var, which the lines output by its script are applied to according to its `:mode`.
+struct ListenVarValue {
+    mode: ListenVarMode,
+    json: serde_json::Value,
+}
+
+impl ListenVarValue {
+    fn new(var: &ListenScriptVar) -> Result<Self> {
+        let json = match var.mode {
+            ListenVarMode::Replace => serde_json::Value::Null,
+            ListenVarMode::JsonMerge | ListenVarMode::JsonPatch => var
+                .initial_value
+                .as_json_value()
+                .with_context(|| format!("The :initial value of listen-var {} is not valid JSON", var.name))?,
+        };
+        Ok(ListenVarValue { mode: var.mode, json })
     }
 
-    async fn stop_all(&mut self) {
-        for (_, token) in self.listen_process_handles.drain() {
-            token.cancel().await;
+    /// Apply a line of output, returning the new value of the variable.
+    fn update(&mut self, line: String) -> Result<DynVal> {
+        match self.mode {
+            ListenVarMode::Replace => return Ok(DynVal::from_string(line)),
+            ListenVarMode::JsonMerge => json_patch::merge(&mut self.json, serde_json::from_str(&line)?),
+            ListenVarMode::JsonPatch => json_patch::apply_patch(&mut self.json, serde_json::from_str(&line)?)?,
         }
+        Ok(DynVal::from(self.json.to_string()))
     }
 }
 
+/// Run the script of a listen-var once, sending every line it outputs as the new value of the variable.
+async fn run_listen_script_once(
+    var: &ListenScriptVar,
+    command: &str,
+    value: &mut ListenVarValue,
+    evt_send: &UnboundedSender<DaemonCommand>,
+    cancel_recv: &mut cancellation::AwaitableCancelationReceiver,
+) -> Result<ListenScriptExit> {
+    let mut handle = unsafe {
+        tokio::process::Command::new("sh")
+            .args(["-c", command])
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped())
+            .stdin(std::process::Stdio::null())
+            .pre_exec(|| {
+                let _ = setpgid(Pid::from_raw(0), Pid::from_raw(0));
+                Ok(())
+            })
+            .spawn()?
+    };
+    let mut stdout_lines = BufReader::new(handle.stdout.take().unwrap()).lines();
+    let mut stderr_lines = BufReader::new(handle.stderr.take().unwrap()).lines();
+    let mut exit = ListenScriptExit::Cancelled(None);
+    crate::loop_select_exiting! {
+        exit_status = handle.wait() => {
+            exit = ListenScriptExit::Exited(exit_status?);
+            break;
+        }
+        notify = cancel_recv.wait_for_cancel() => {
+            exit = ListenScriptExit::Cancelled(notify);
+            break;
+        }
+        Ok(Some(line)) = stdout_lines.next_line() => match value.update(line) {
+            Ok(new_value) => evt_send.send(DaemonCommand::UpdateVars(vec![(var.name.to_owned(), new_value)]))?,
+            Err(err) => log::warn!("Ignoring invalid output of listen-var {}: {:?}", var.name, err),
+        },
+        Ok(Some(line)) = stderr_lines.next_line() => {
+            log::warn!("stderr of `{}`: {}", var.name, line);
+        }
+        else => break,
+    };
+    terminate_handle(handle).await;
+    Ok(exit)
+}
+
 impl Drop for ListenVarHandler {
     fn drop(&mut self) {
         if !self.listen_process_handles.is_empty() {
@@ -372,3 +726,185 @@ mod cancellation {
         (AwaitableCancelationSender(send), AwaitableCancelationReceiver(recv))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::{temp_dir, write_file};
+    use yuck::config::script_var_definition::FileVarFormat;
+
+    #[tokio::test]
+    async fn test_file_var_atomic_replace() {
+        let dir = temp_dir("watch-file-var");
+        write_file(dir.join("state"), "a");
+        let var = FileVar {
+            name: VarName::from("foo"),
+            path: dir.join("state").to_string_lossy().to_string(),
+            format: FileVarFormat::Text,
+            initial_value: DynVal::from(""),
+            name_span: eww_shared_util::Span::DUMMY,
+        };
+        let (evt_send, mut evt_recv) = tokio::sync::mpsc::unbounded_channel();
+        let mut handler = FileVarHandler::new(evt_send).unwrap();
+        handler.start(var);
+        let mut next_value = async || match evt_recv.recv().await.unwrap() {
+            DaemonCommand::UpdateVars(mut vars) => vars.remove(0).1,
+            _ => unreachable!(),
+        };
+        assert_eq!(next_value().await, DynVal::from("a"));
+
+        std::fs::write(dir.join("state.tmp"), "b").unwrap();
+        std::fs::rename(dir.join("state.tmp"), dir.join("state")).unwrap();
+        assert_eq!(next_value().await, DynVal::from("b"));
+
+        std::fs::write(dir.join("state"), "c").unwrap();
+        assert_eq!(next_value().await, DynVal::from("c"));
+        handler.stop_all();
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_listen_var_restart() {
+        let var = ListenScriptVar {
+            name: VarName::from("foo"),
+            command: ListenVarSource::Shell(eww_shared_util::Span::DUMMY, "exit 3".to_string()),
+            initial_value: DynVal::from(""),
+            mode: ListenVarMode::Replace,
+            restart: RestartPolicy::OnFailure,
+            restart_delay: std::time::Duration::from_millis(10),
+            max_retries: Some(2),
+            name_span: eww_shared_util::Span::DUMMY,
+        };
+        let (evt_send, mut evt_recv) = tokio::sync::mpsc::unbounded_channel();
+        let mut handler = ListenVarHandler::new(evt_send).unwrap();
+        handler.start(var).await;
+
+        let mut statuses = Vec::new();
+        while let Ok(Some(DaemonCommand::UpdateVars(mut vars))) =
+            tokio::time::timeout(std::time::Duration::from_secs(1), evt_recv.recv()).await
+        {
+            let (name, value) = vars.remove(0);
+            assert_eq!(name, VarName::from("foo__status"));
+            statuses.push(value);
+        }
+        let status = |running, exit_code, restarts| ListenVarStatus { running, exit_code, restarts }.to_dynval();
+        assert_eq!(
+            statuses,
+            vec![
+                status(true, None, 0),
+                status(false, Some(3), 0),
+                status(true, None, 1),
+                status(false, Some(3), 1),
+                status(true, None, 2),
+                status(false, Some(3), 2),
+            ]
+        );
+        handler.stop_all().await;
+    }
+
+    #[tokio::test]
+    async fn test_poll_var_errors() {
+        let var = PollScriptVar {
+            name: VarName::from("foo"),
+            run_while_expr: simplexpr::SimplExpr::Literal(DynVal::from(true)),
+            command: VarSource::Shell(eww_shared_util::Span::DUMMY, "sleep 10".to_string()),
+            initial_value: Some(DynVal::from("")),
+            interval: std::time::Duration::from_secs(1),
+            timeout: Some(std::time::Duration::from_millis(50)),
+            on_error: PollErrorBehavior::Value,
+            error_value: Some(simplexpr::SimplExpr::Literal(DynVal::from("N/A"))),
+            allowed_paths: Vec::new(),
+            name_span: eww_shared_util::Span::DUMMY,
+        };
+        let (evt_send, mut evt_recv) = tokio::sync::mpsc::unbounded_channel();
+        let mut failing = false;
+        assert!(poll_once(&var, &evt_send, &mut failing).await.is_err());
+        let DaemonCommand::UpdateVars(updates) = evt_recv.recv().await.unwrap() else { unreachable!() };
+        assert_eq!(updates, vec![(VarName::from("foo__error"), DynVal::from("Timed out after 50ms"))]);
+        let DaemonCommand::UpdateVarFromExpr(name, expr) = evt_recv.recv().await.unwrap() else { unreachable!() };
+        assert_eq!((name, expr), (VarName::from("foo"), simplexpr::SimplExpr::Literal(DynVal::from("N/A"))));
+
+        // the error is only reset once the variable recovers
+        let var = PollScriptVar { command: VarSource::Shell(eww_shared_util::Span::DUMMY, "echo 1".to_string()), ..var };
+        poll_once(&var, &evt_send, &mut failing).await.unwrap();
+        let DaemonCommand::UpdateVars(updates) = evt_recv.recv().await.unwrap() else { unreachable!() };
+        assert_eq!(updates, vec![(VarName::from("foo"), DynVal::from("1")), (VarName::from("foo__error"), DynVal::from(""))]);
+        poll_once(&var, &evt_send, &mut failing).await.unwrap();
+        let DaemonCommand::UpdateVars(updates) = evt_recv.recv().await.unwrap() else { unreachable!() };
+        assert_eq!(updates, vec![(VarName::from("foo"), DynVal::from("1"))]);
+
+        // builtin variables have no error variable
+        let var =
+            PollScriptVar { command: VarSource::Function(|| Err("failed".into())), on_error: PollErrorBehavior::Keep, ..var };
+        assert!(poll_once(&var, &evt_send, &mut failing).await.is_err());
+        assert!(evt_recv.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_poll_var_expression_timeout() {
+        let dir = temp_dir("poll-expression-timeout");
+        // reading from a fifo blocks until something writes to it
+        let fifo = dir.join("fifo");
+        assert!(std::process::Command::new("mkfifo").arg(&fifo).status().unwrap().success());
+        let var = PollScriptVar {
+            name: VarName::from("foo"),
+            run_while_expr: simplexpr::SimplExpr::Literal(DynVal::from(true)),
+            command: VarSource::Expression(
+                simplexpr::parse_string(0, 0, &format!(r#"read_file("{}")"#, fifo.display())).unwrap(),
+            ),
+            initial_value: Some(DynVal::from("")),
+            interval: std::time::Duration::from_secs(1),
+            timeout: Some(std::time::Duration::from_millis(50)),
+            on_error: PollErrorBehavior::Keep,
+            error_value: None,
+            allowed_paths: vec![dir.display().to_string()],
+            name_span: eww_shared_util::Span::DUMMY,
+        };
+        let (evt_send, mut evt_recv) = tokio::sync::mpsc::unbounded_channel();
+        assert!(poll_once(&var, &evt_send, &mut false).await.is_err());
+        let DaemonCommand::UpdateVars(updates) = evt_recv.recv().await.unwrap() else { unreachable!() };
+        assert_eq!(updates, vec![(VarName::from("foo__error"), DynVal::from("Timed out after 50ms"))]);
+        // unblock the abandoned read, as the runtime waits for it on shutdown
+        std::fs::write(&fifo, "").unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_listen_var_json_modes() {
+        let var = |mode, command: &str| ListenScriptVar {
+            name: VarName::from("foo"),
+            command: ListenVarSource::Shell(eww_shared_util::Span::DUMMY, command.to_string()),
+            initial_value: DynVal::from(r#"{"a":1}"#),
+            mode,
+            restart: RestartPolicy::Never,
+            restart_delay: ListenScriptVar::DEFAULT_RESTART_DELAY,
+            max_retries: None,
+            name_span: eww_shared_util::Span::DUMMY,
+        };
+        let mut value = ListenVarValue::new(&var(ListenVarMode::JsonMerge, "")).unwrap();
+        assert_eq!(value.update(r#"{"b":{"c":2}}"#.to_string()).unwrap(), DynVal::from(r#"{"a":1,"b":{"c":2}}"#));
+        assert!(value.update("not json".to_string()).is_err());
+        assert_eq!(value.update(r#"{"a":null}"#.to_string()).unwrap(), DynVal::from(r#"{"b":{"c":2}}"#));
+
+        let mut value = ListenVarValue::new(&var(ListenVarMode::JsonPatch, "")).unwrap();
+        let patch = r#"[{"op":"add","path":"/list","value":[]},{"op":"add","path":"/list/-","value":"x"}]"#;
+        assert_eq!(value.update(patch.to_string()).unwrap(), DynVal::from(r#"{"a":1,"list":["x"]}"#));
+        assert!(value.update(r#"[{"op":"remove","path":"/missing"}]"#.to_string()).is_err());
+        let invalid_initial = ListenScriptVar { initial_value: DynVal::from("not json"), ..var(ListenVarMode::JsonMerge, "") };
+        assert!(ListenVarValue::new(&invalid_initial).is_err());
+
+        let (evt_send, mut evt_recv) = tokio::sync::mpsc::unbounded_channel();
+        let mut handler = ListenVarHandler::new(evt_send).unwrap();
+        handler.start(var(ListenVarMode::JsonMerge, r#"echo '{"b":2}'; echo '{"c":3}'; sleep 10"#)).await;
+        let mut values = Vec::new();
+        while values.len() < 2 {
+            let DaemonCommand::UpdateVars(mut vars) = evt_recv.recv().await.unwrap() else { unreachable!() };
+            let (name, value) = vars.remove(0);
+            if name == VarName::from("foo") {
+                values.push(value);
+            }
+        }
+        assert_eq!(values, vec![DynVal::from(r#"{"a":1,"b":2}"#), DynVal::from(r#"{"a":1,"b":2,"c":3}"#)]);
+        handler.stop_all().await;
+    }
+}
@@ -3,7 +3,7 @@ use crate::{
     app::{self, App, DaemonCommand},
     config, daemon_response,
     display_backend::DisplayBackend,
-    error_handling_ctx, ipc_server, script_var_handler,
+    error_handling_ctx, ipc_server, notification_server, script_var_handler,
     state::scope_graph::ScopeGraph,
 };
 use anyhow::{Context, Result};
@@ -106,7 +106,11 @@ pub fn initialize_server<B: DisplayBackend>(
     }
 
     // initialize all the handlers and tasks running asyncronously
-    let tokio_handle = init_async_part(app.paths.clone(), ui_send);
+    let tokio_handle = init_async_part(app.paths.clone(), ui_send.clone());
+    {
+        let _g = tokio_handle.enter();
+        notification_server::configure(app.eww_config.get_notification_server().cloned(), ui_send);
+    }
 
     gtk::glib::MainContext::default().spawn_local(async move {
         // if an action was given to the daemon initially, execute it first.
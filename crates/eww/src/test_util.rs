@@ -0,0 +1,18 @@
+//! Helpers shared between the tests of different modules.
+
+use std::path::PathBuf;
+
+/// Create an empty directory in the systems temp dir, i.e. to build a fake sysfs tree or place stand-in sockets in.
+/// `name` must be unique across all tests, as they run in parallel.
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("eww-test-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Write a file, creating its parent directories if necessary.
+pub fn write_file(path: PathBuf, content: &str) {
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(path, content).unwrap();
+}
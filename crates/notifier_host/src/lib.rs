@@ -29,6 +29,9 @@
 //!
 //! If there are multiple trays running on the system, there can be multiple `StatusNotifierHost`s,
 //! but only one `StatusNotifierWatcher` (usually from whatever tray was started first).
+//!
+//! Additionally, this crate provides an optional [`NotificationServer`], implementing the
+//! `org.freedesktop.Notifications` service so that notifications can be shown by the bar itself.
 
 pub mod proxy;
 
@@ -41,6 +44,9 @@ pub use icon::*;
 mod item;
 pub use item::*;
 
+mod notifications;
+pub use notifications::*;
+
 mod watcher;
 pub use watcher::*;
 
@@ -49,4 +55,7 @@ pub(crate) mod names {
     pub const WATCHER_OBJECT: &str = "/StatusNotifierWatcher";
 
     pub const ITEM_OBJECT: &str = "/StatusNotifierItem";
+
+    pub const NOTIFICATIONS_BUS: &str = "org.freedesktop.Notifications";
+    pub const NOTIFICATIONS_OBJECT: &str = "/org/freedesktop/Notifications";
 }
@@ -0,0 +1,362 @@
+use crate::names;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use zbus::{interface, object_server::SignalEmitter, zvariant::OwnedValue};
+
+/// How urgent a [`Notification`] is, as given by the `urgency` hint of the sender.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Urgency {
+    Low,
+    #[default]
+    Normal,
+    Critical,
+}
+
+/// A notification received by the [`NotificationServer`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Notification {
+    pub id: u32,
+    pub app_name: String,
+    /// Icon name or path of the notification. Falls back to the `image-path` hint if the sender didn't give an icon.
+    pub app_icon: String,
+    pub summary: String,
+    pub body: String,
+    /// Pairs of action keys and their human readable labels
+    pub actions: Vec<(String, String)>,
+    pub urgency: Urgency,
+    /// Timeout in milliseconds after which the notification expires, as requested by the sender.
+    /// A timeout of `0` or less means the notification never expires.
+    pub expire_timeout: i32,
+    /// UNIX timestamp of when the notification was received
+    pub timestamp: u64,
+    /// Expired notifications are kept in the history until they are dismissed or pushed out by newer notifications.
+    pub expired: bool,
+}
+
+/// Reasons for closing a notification, as defined by the specification.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CloseReason {
+    Expired = 1,
+    Dismissed = 2,
+    Closed = 3,
+}
+
+type ChangeCallback = Box<dyn Fn(&[Notification]) + Send + Sync>;
+
+struct State {
+    /// Notifications, newest first
+    notifications: Vec<Notification>,
+    next_id: u32,
+    history_size: usize,
+    expiry_timers: HashMap<u32, tokio::task::AbortHandle>,
+    on_change: ChangeCallback,
+}
+
+impl State {
+    /// Insert a notification, replacing an existing one with the same id in place.
+    fn insert(&mut self, notification: Notification) {
+        if let Some(timer) = self.expiry_timers.remove(&notification.id) {
+            timer.abort();
+        }
+        match self.notifications.iter_mut().find(|x| x.id == notification.id) {
+            Some(existing) => *existing = notification,
+            None => self.notifications.insert(0, notification),
+        }
+        self.truncate();
+    }
+
+    fn remove(&mut self, id: u32) -> bool {
+        if let Some(timer) = self.expiry_timers.remove(&id) {
+            timer.abort();
+        }
+        let len = self.notifications.len();
+        self.notifications.retain(|x| x.id != id);
+        self.notifications.len() != len
+    }
+
+    fn truncate(&mut self) {
+        for removed in self.notifications.drain(self.history_size.min(self.notifications.len())..) {
+            if let Some(timer) = self.expiry_timers.remove(&removed.id) {
+                timer.abort();
+            }
+        }
+    }
+
+    fn changed(&self) {
+        (self.on_change)(&self.notifications);
+    }
+}
+
+/// An implementation of the [`org.freedesktop.Notifications`] service, which keeps received notifications in a list
+/// of at most `history_size` entries, calling a callback whenever that list changes.
+///
+/// [`org.freedesktop.Notifications`]: https://specifications.freedesktop.org/notification-spec/latest/
+pub struct NotificationServer {
+    state: Arc<Mutex<State>>,
+}
+
+#[interface(name = "org.freedesktop.Notifications")]
+impl NotificationServer {
+    /// GetCapabilities method
+    fn get_capabilities(&self) -> Vec<&str> {
+        vec!["actions", "body", "persistence"]
+    }
+
+    /// Notify method
+    #[allow(clippy::too_many_arguments)]
+    async fn notify(
+        &self,
+        app_name: String,
+        replaces_id: u32,
+        app_icon: String,
+        summary: String,
+        body: String,
+        actions: Vec<String>,
+        hints: HashMap<String, OwnedValue>,
+        expire_timeout: i32,
+        #[zbus(signal_context)] ctxt: SignalEmitter<'_>,
+    ) -> u32 {
+        let urgency = match hints.get("urgency").and_then(|x| x.downcast_ref::<u8>().ok()) {
+            Some(0) => Urgency::Low,
+            Some(2) => Urgency::Critical,
+            _ => Urgency::Normal,
+        };
+        let app_icon = if app_icon.is_empty() {
+            ["image-path", "image_path"]
+                .iter()
+                .find_map(|hint| hints.get(*hint).and_then(|x| x.downcast_ref::<String>().ok()))
+                .unwrap_or_default()
+        } else {
+            app_icon
+        };
+        let timestamp =
+            std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map(|x| x.as_secs()).unwrap_or_default();
+
+        let mut state = self.state.lock().unwrap(); // unwrap: mutex poisoning is okay
+        let id = if replaces_id == 0 {
+            state.next_id += 1;
+            state.next_id
+        } else {
+            replaces_id
+        };
+        state.insert(Notification {
+            id,
+            app_name,
+            app_icon,
+            summary,
+            body,
+            actions: actions.chunks_exact(2).map(|x| (x[0].clone(), x[1].clone())).collect(),
+            urgency,
+            expire_timeout,
+            timestamp,
+            expired: false,
+        });
+
+        if expire_timeout > 0 {
+            let timer = tokio::spawn({
+                let state = self.state.clone();
+                let ctxt = ctxt.to_owned();
+                async move {
+                    tokio::time::sleep(std::time::Duration::from_millis(expire_timeout as u64)).await;
+                    {
+                        let mut state = state.lock().unwrap(); // unwrap: mutex poisoning is okay
+                        state.expiry_timers.remove(&id);
+                        if let Some(notification) = state.notifications.iter_mut().find(|x| x.id == id) {
+                            notification.expired = true;
+                        }
+                        state.changed();
+                    }
+                    if let Err(e) = NotificationServer::notification_closed(&ctxt, id, CloseReason::Expired as u32).await {
+                        log::error!("failed to signal NotificationClosed: {}", e);
+                    }
+                }
+            });
+            state.expiry_timers.insert(id, timer.abort_handle());
+        }
+        state.changed();
+        id
+    }
+
+    /// CloseNotification method
+    async fn close_notification(&self, id: u32, #[zbus(signal_context)] ctxt: SignalEmitter<'_>) -> zbus::fdo::Result<()> {
+        let removed = {
+            let mut state = self.state.lock().unwrap(); // unwrap: mutex poisoning is okay
+            let removed = state.remove(id);
+            if removed {
+                state.changed();
+            }
+            removed
+        };
+        if removed {
+            NotificationServer::notification_closed(&ctxt, id, CloseReason::Closed as u32).await?;
+        }
+        Ok(())
+    }
+
+    /// GetServerInformation method
+    fn get_server_information(&self) -> (&str, &str, &str, &str) {
+        ("eww", "elkowar", env!("CARGO_PKG_VERSION"), "1.2")
+    }
+
+    /// NotificationClosed signal
+    #[zbus(signal)]
+    async fn notification_closed(ctxt: &SignalEmitter<'_>, id: u32, reason: u32) -> zbus::Result<()>;
+
+    /// ActionInvoked signal
+    #[zbus(signal)]
+    async fn action_invoked(ctxt: &SignalEmitter<'_>, id: u32, action_key: &str) -> zbus::Result<()>;
+}
+
+impl NotificationServer {
+    /// Create a new NotificationServer, calling `on_change` with all notifications whenever they change.
+    pub fn new(history_size: usize, on_change: impl Fn(&[Notification]) + Send + Sync + 'static) -> Self {
+        let state = State {
+            notifications: Vec::new(),
+            next_id: 0,
+            history_size,
+            expiry_timers: HashMap::new(),
+            on_change: Box::new(on_change),
+        };
+        NotificationServer { state: Arc::new(Mutex::new(state)) }
+    }
+
+    /// Attach and run the NotificationServer on a connection.
+    ///
+    /// Unlike the [`Watcher`][`crate::Watcher`], this fails if another notification daemon is already running,
+    /// as there can only be one.
+    pub async fn attach_to(self, con: &zbus::Connection) -> zbus::Result<NotificationServerHandle> {
+        let state = self.state.clone();
+        if !con.object_server().at(names::NOTIFICATIONS_OBJECT, self).await? {
+            return Err(zbus::Error::Failure(format!(
+                "Object already exists at {} on this connection -- is the notification server already running?",
+                names::NOTIFICATIONS_OBJECT
+            )));
+        }
+
+        let flags = [zbus::fdo::RequestNameFlags::DoNotQueue];
+        match con.request_name_with_flags(names::NOTIFICATIONS_BUS, flags.into_iter().collect()).await {
+            Ok(zbus::fdo::RequestNameReply::PrimaryOwner | zbus::fdo::RequestNameReply::AlreadyOwner) => {
+                Ok(NotificationServerHandle { con: con.clone(), state })
+            }
+            Ok(_) | Err(zbus::Error::NameTaken) => {
+                con.object_server().remove::<NotificationServer, _>(names::NOTIFICATIONS_OBJECT).await?;
+                Err(zbus::Error::Failure(format!("{} is already owned by another notification daemon", names::NOTIFICATIONS_BUS)))
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// Handle to a running [`NotificationServer`], used to manage its notifications.
+#[derive(Clone)]
+pub struct NotificationServerHandle {
+    con: zbus::Connection,
+    state: Arc<Mutex<State>>,
+}
+
+impl NotificationServerHandle {
+    /// Get all notifications, newest first.
+    pub fn notifications(&self) -> Vec<Notification> {
+        self.state.lock().unwrap().notifications.clone() // unwrap: mutex poisoning is okay
+    }
+
+    /// Change the amount of notifications that are kept. This also calls the change callback, even if the size didn't change.
+    pub fn set_history_size(&self, history_size: usize) {
+        let mut state = self.state.lock().unwrap(); // unwrap: mutex poisoning is okay
+        state.history_size = history_size;
+        state.truncate();
+        state.changed();
+    }
+
+    /// Dismiss a notification, removing it from the list. Returns false if there is no notification with the given id.
+    pub async fn dismiss(&self, id: u32) -> zbus::Result<bool> {
+        let removed = {
+            let mut state = self.state.lock().unwrap(); // unwrap: mutex poisoning is okay
+            let removed = state.remove(id);
+            if removed {
+                state.changed();
+            }
+            removed
+        };
+        if removed {
+            NotificationServer::notification_closed(&self.signal_emitter()?, id, CloseReason::Dismissed as u32).await?;
+        }
+        Ok(removed)
+    }
+
+    /// Dismiss all notifications.
+    pub async fn dismiss_all(&self) -> zbus::Result<()> {
+        let ids: Vec<u32> = self.notifications().iter().map(|x| x.id).collect();
+        for id in ids {
+            self.dismiss(id).await?;
+        }
+        Ok(())
+    }
+
+    /// Invoke an action of a notification, which is then dismissed.
+    /// Returns false if there is no notification with the given id, or it doesn't have the given action.
+    pub async fn invoke_action(&self, id: u32, action_key: &str) -> zbus::Result<bool> {
+        let has_action = self.notifications().iter().any(|x| x.id == id && x.actions.iter().any(|(key, _)| key == action_key));
+        if !has_action {
+            return Ok(false);
+        }
+        NotificationServer::action_invoked(&self.signal_emitter()?, id, action_key).await?;
+        self.dismiss(id).await
+    }
+
+    /// Stop the server, releasing its bus name.
+    pub async fn stop(self) -> zbus::Result<()> {
+        {
+            let mut state = self.state.lock().unwrap(); // unwrap: mutex poisoning is okay
+            state.expiry_timers.drain().for_each(|(_, timer)| timer.abort());
+        }
+        self.con.object_server().remove::<NotificationServer, _>(names::NOTIFICATIONS_OBJECT).await?;
+        self.con.release_name(names::NOTIFICATIONS_BUS).await?;
+        Ok(())
+    }
+
+    fn signal_emitter(&self) -> zbus::Result<SignalEmitter<'static>> {
+        SignalEmitter::new(&self.con, names::NOTIFICATIONS_OBJECT)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn notification(id: u32) -> Notification {
+        Notification {
+            id,
+            app_name: "test".to_string(),
+            app_icon: String::new(),
+            summary: format!("notification {}", id),
+            body: String::new(),
+            actions: Vec::new(),
+            urgency: Urgency::Normal,
+            expire_timeout: 0,
+            timestamp: 0,
+            expired: false,
+        }
+    }
+
+    #[test]
+    fn test_history() {
+        let server = NotificationServer::new(2, |_| {});
+        let mut state = server.state.lock().unwrap();
+        state.insert(notification(1));
+        state.insert(notification(2));
+        state.insert(notification(3));
+        assert_eq!(state.notifications.iter().map(|x| x.id).collect::<Vec<_>>(), vec![3, 2]);
+
+        let mut replacement = notification(2);
+        replacement.summary = "replaced".to_string();
+        state.insert(replacement.clone());
+        assert_eq!(state.notifications, vec![notification(3), replacement]);
+
+        assert!(state.remove(3));
+        assert!(!state.remove(3));
+        assert_eq!(state.notifications.len(), 1);
+    }
+}
@@ -12,6 +12,7 @@ pub enum BinOp {
     #[strum(serialize = "-") ] Minus,
     #[strum(serialize = "*") ] Times,
     #[strum(serialize = "/") ] Div,
+    #[strum(serialize = "//")] IntDiv,
     #[strum(serialize = "%") ] Mod,
     #[strum(serialize = "==")] Equals,
     #[strum(serialize = "!=")] NotEquals,
@@ -52,6 +53,10 @@ pub enum SimplExpr {
     IfElse(Span, Box<SimplExpr>, Box<SimplExpr>, Box<SimplExpr>),
     JsonAccess(Span, AccessType, Box<SimplExpr>, Box<SimplExpr>),
     FunctionCall(Span, String, Vec<SimplExpr>),
+    /// A lambda like `x => x.name`, which may only be passed to functions like `map`
+    Lambda(Span, Vec<VarName>, Box<SimplExpr>),
+    /// `let name = value in body`, where `name` is only visible within `body`
+    Let(Span, VarName, Box<SimplExpr>, Box<SimplExpr>),
 }
 
 impl std::fmt::Display for SimplExpr {
@@ -77,6 +82,11 @@ impl std::fmt::Display for SimplExpr {
             SimplExpr::FunctionCall(_, function_name, args) => {
                 write!(f, "{}({})", function_name, args.iter().join(", "))
             }
+            SimplExpr::Lambda(_, params, body) => match params.as_slice() {
+                [param] => write!(f, "{} => {}", param, body),
+                _ => write!(f, "({}) => {}", params.iter().join(", "), body),
+            },
+            SimplExpr::Let(_, name, value, body) => write!(f, "(let {} = {} in {})", name, value, body),
             SimplExpr::JsonArray(_, values) => write!(f, "[{}]", values.iter().join(", ")),
             SimplExpr::JsonObject(_, entries) => {
                 write!(f, "{{{}}}", entries.iter().map(|(k, v)| format!("{}: {}", k, v)).join(", "))
@@ -112,6 +122,8 @@ impl SimplExpr {
             JsonAccess(_, _, a, b) | BinOp(_, a, _, b) => a.references_var(var) || b.references_var(var),
             UnaryOp(_, _, x) => x.references_var(var),
             IfElse(_, a, b, c) => a.references_var(var) || b.references_var(var) || c.references_var(var),
+            Lambda(_, params, body) => !params.contains(var) && body.references_var(var),
+            Let(_, name, value, body) => value.references_var(var) || (name != var && body.references_var(var)),
             VarRef(_, x) => x == var,
         }
     }
@@ -135,6 +147,13 @@ impl SimplExpr {
                 k.collect_var_refs_into(dest);
                 v.collect_var_refs_into(dest);
             }),
+            Lambda(_, params, body) => {
+                dest.extend(body.collect_var_refs().into_iter().filter(|x| !params.contains(x)));
+            }
+            Let(_, name, value, body) => {
+                value.collect_var_refs_into(dest);
+                dest.extend(body.collect_var_refs().into_iter().filter(|x| x != name));
+            }
             Literal(_) => {}
         };
     }
@@ -159,6 +178,8 @@ impl Spanned for SimplExpr {
             SimplExpr::IfElse(span, ..) => *span,
             SimplExpr::JsonAccess(span, ..) => *span,
             SimplExpr::FunctionCall(span, ..) => *span,
+            SimplExpr::Lambda(span, ..) => *span,
+            SimplExpr::Let(span, ..) => *span,
         }
     }
 }
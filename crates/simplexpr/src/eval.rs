@@ -60,12 +60,28 @@ pub enum EvalError {
     #[error("Error parsing date: {0}")]
     ChronoError(String),
 
+    #[error("Error in function {0}: {1}")]
+    FunctionFailed(String, String),
+
+    #[error("Lambdas can only be passed to functions like map or filter")]
+    UnexpectedLambda,
+
+    #[error("Integer division by zero")]
+    DivisionByZero,
+
+    #[error("Function {0} expects a lambda taking {1} argument(s)")]
+    ExpectedLambda(String, usize),
+
     #[error("{1}")]
     Spanned(Span, Box<EvalError>),
 }
 
 static_assertions::assert_impl_all!(EvalError: Send, Sync);
 
+/// Functions that are only available in some contexts, such as scripts of polled variables.
+/// Returns `None` for functions it doesn't know, which are then looked up in the builtin functions.
+pub type ExtraFunctions<'a> = dyn Fn(&str, &[DynVal]) -> Option<Result<DynVal, EvalError>> + 'a;
+
 impl EvalError {
     pub fn at(self, span: Span) -> Self {
         match self {
@@ -111,6 +127,19 @@ impl SimplExpr {
                 FunctionCall(span, name, args.into_iter().map(|x| x.try_map_var_refs(f)).collect::<Result<_, _>>()?)
             }
             VarRef(span, name) => f(span, name)?,
+            // the parameters of a lambda shadow any variables of the same name.
+            // The closure is passed as a trait object, as its type would otherwise grow with every nested lambda
+            Lambda(span, params, body) => {
+                let shadowed: &dyn Fn(Span, VarName) -> Result<SimplExpr, E> =
+                    &|span, name| if params.contains(&name) { Ok(VarRef(span, name)) } else { f(span, name) };
+                Lambda(span, params.clone(), Box::new(body.try_map_var_refs(shadowed)?))
+            }
+            Let(span, name, value, body) => {
+                let shadowed: &dyn Fn(Span, VarName) -> Result<SimplExpr, E> =
+                    &|span, var| if var == name { Ok(VarRef(span, var)) } else { f(span, var) };
+                let body = body.try_map_var_refs(shadowed)?;
+                Let(span, name, Box::new(value.try_map_var_refs(f)?), Box::new(body))
+            }
             JsonArray(span, values) => {
                 JsonArray(span, values.into_iter().map(|x| x.try_map_var_refs(f)).collect::<Result<_, _>>()?)
             }
@@ -167,6 +196,12 @@ impl SimplExpr {
                 refs
             }
             FunctionCall(_, _, args) => args.iter().flat_map(|a| a.var_refs_with_span()).collect(),
+            Lambda(_, params, body) => body.var_refs_with_span().into_iter().filter(|(_, name)| !params.contains(name)).collect(),
+            Let(_, name, value, body) => {
+                let mut refs = value.var_refs_with_span();
+                refs.extend(body.var_refs_with_span().into_iter().filter(|(_, var)| *var != name));
+                refs
+            }
             JsonArray(_, values) => values.iter().flat_map(|v| v.var_refs_with_span()).collect(),
             JsonObject(_, entries) => {
                 entries.iter().flat_map(|(k, v)| k.var_refs_with_span().into_iter().chain(v.var_refs_with_span())).collect()
@@ -185,28 +220,42 @@ impl SimplExpr {
     }
 
     pub fn eval(&self, values: &HashMap<VarName, DynVal>) -> Result<DynVal, EvalError> {
+        self.eval_with_functions(values, &|_, _| None)
+    }
+
+    /// Evaluate the expression, with `functions` being available in addition to the builtin functions.
+    pub fn eval_with_functions(
+        &self,
+        values: &HashMap<VarName, DynVal>,
+        functions: &ExtraFunctions,
+    ) -> Result<DynVal, EvalError> {
+        self.eval_in_scope(Scope::Vars(values), functions)
+    }
+
+    fn eval_in_scope(&self, scope: Scope, functions: &ExtraFunctions) -> Result<DynVal, EvalError> {
         let span = self.span();
         let value = match self {
             SimplExpr::Literal(x) => Ok(x.clone()),
             SimplExpr::Concat(span, elems) => {
                 let mut output = String::new();
                 for elem in elems {
-                    let result = elem.eval(values)?;
+                    let result = elem.eval_in_scope(scope, functions)?;
                     output.push_str(&result.0);
                 }
                 Ok(DynVal(output, *span))
             }
             SimplExpr::VarRef(span, name) => {
-                let similar_ish = values.keys().filter(|keys| strsim::levenshtein(&keys.0, &name.0) < 3).cloned().collect_vec();
-                Ok(values
+                let similar_ish =
+                    || scope.names().filter(|keys| strsim::levenshtein(&keys.0, &name.0) < 3).cloned().collect_vec();
+                Ok(scope
                     .get(name)
                     .cloned()
-                    .ok_or_else(|| EvalError::UnknownVariable(name.clone(), similar_ish).at(*span))?
+                    .ok_or_else(|| EvalError::UnknownVariable(name.clone(), similar_ish()).at(*span))?
                     .at(*span))
             }
             SimplExpr::BinOp(span, a, op, b) => {
-                let a = a.eval(values)?;
-                let b = || b.eval(values);
+                let a = a.eval_in_scope(scope, functions)?;
+                let b = || b.eval_in_scope(scope, functions);
                 // Lazy operators
                 let dynval = match op {
                     BinOp::And => DynVal::from(a.as_bool()? && b()?.as_bool()?),
@@ -228,6 +277,10 @@ impl SimplExpr {
                             BinOp::Minus => DynVal::from(a.as_f64()? - b.as_f64()?),
                             BinOp::Times => DynVal::from(a.as_f64()? * b.as_f64()?),
                             BinOp::Div => DynVal::from(a.as_f64()? / b.as_f64()?),
+                            BinOp::IntDiv => match (a.as_f64()?, b.as_f64()?) {
+                                (_, 0.0) => return Err(EvalError::DivisionByZero.at(*span)),
+                                (a, b) => DynVal::from((a / b).floor()),
+                            },
                             BinOp::Mod => DynVal::from(a.as_f64()? % b.as_f64()?),
                             BinOp::GT => DynVal::from(a.as_f64()? > b.as_f64()?),
                             BinOp::LT => DynVal::from(a.as_f64()? < b.as_f64()?),
@@ -244,22 +297,22 @@ impl SimplExpr {
                 Ok(dynval.at(*span))
             }
             SimplExpr::UnaryOp(span, op, a) => {
-                let a = a.eval(values)?;
+                let a = a.eval_in_scope(scope, functions)?;
                 Ok(match op {
                     UnaryOp::Not => DynVal::from(!a.as_bool()?).at(*span),
                     UnaryOp::Negative => DynVal::from(-a.as_f64()?).at(*span),
                 })
             }
             SimplExpr::IfElse(_, cond, yes, no) => {
-                if cond.eval(values)?.as_bool()? {
-                    yes.eval(values)
+                if cond.eval_in_scope(scope, functions)?.as_bool()? {
+                    yes.eval_in_scope(scope, functions)
                 } else {
-                    no.eval(values)
+                    no.eval_in_scope(scope, functions)
                 }
             }
             SimplExpr::JsonAccess(span, safe, val, index) => {
-                let val = val.eval(values)?;
-                let index = index.eval(values)?;
+                let val = val.eval_in_scope(scope, functions)?;
+                let index = index.eval_in_scope(scope, functions)?;
 
                 let is_safe = *safe == AccessType::Safe;
 
@@ -284,30 +337,177 @@ impl SimplExpr {
                     _ => Err(EvalError::CannotIndex(format!("{}", val)).at(*span)),
                 }
             }
+            SimplExpr::FunctionCall(span, function_name, args) if LAMBDA_FUNCTIONS.contains(&function_name.as_str()) => {
+                call_lambda_function(function_name, args, scope, functions).map(|x| x.at(*span)).map_err(|e| e.at(*span))
+            }
             SimplExpr::FunctionCall(span, function_name, args) => {
-                let args = args.iter().map(|a| a.eval(values)).collect::<Result<_, EvalError>>()?;
-                call_expr_function(function_name, args).map(|x| x.at(*span)).map_err(|e| e.at(*span))
+                let args = args.iter().map(|a| a.eval_in_scope(scope, functions)).collect::<Result<Vec<_>, EvalError>>()?;
+                match functions(function_name, &args) {
+                    Some(result) => result,
+                    None => call_expr_function(function_name, args),
+                }
+                .map(|x| x.at(*span))
+                .map_err(|e| e.at(*span))
             }
             SimplExpr::JsonArray(span, entries) => {
                 let entries = entries
                     .iter()
-                    .map(|v| Ok(serde_json::Value::String(v.eval(values)?.as_string()?)))
+                    .map(|v| Ok(serde_json::Value::String(v.eval_in_scope(scope, functions)?.as_string()?)))
                     .collect::<Result<_, EvalError>>()?;
                 Ok(DynVal::try_from(serde_json::Value::Array(entries))?.at(*span))
             }
             SimplExpr::JsonObject(span, entries) => {
                 let entries = entries
                     .iter()
-                    .map(|(k, v)| Ok((k.eval(values)?.as_string()?, serde_json::Value::String(v.eval(values)?.as_string()?))))
+                    .map(|(k, v)| {
+                        Ok((
+                            k.eval_in_scope(scope, functions)?.as_string()?,
+                            serde_json::Value::String(v.eval_in_scope(scope, functions)?.as_string()?),
+                        ))
+                    })
                     .collect::<Result<_, EvalError>>()?;
                 Ok(DynVal::try_from(serde_json::Value::Object(entries))?.at(*span))
             }
+            SimplExpr::Lambda(span, ..) => Err(EvalError::UnexpectedLambda.at(*span)),
+            SimplExpr::Let(_, name, value, body) => {
+                let value = value.eval_in_scope(scope, functions)?;
+                body.eval_in_scope(Scope::Binding(&scope, name, &value), functions)
+            }
         };
         Ok(value?.at(span))
     }
 }
 
+/// The variables an expression is evaluated with, and the bindings of the `let` expressions and lambdas surrounding it,
+/// which shadow any variables of the same name.
+#[derive(Clone, Copy)]
+enum Scope<'a> {
+    Vars(&'a HashMap<VarName, DynVal>),
+    Binding(&'a Scope<'a>, &'a VarName, &'a DynVal),
+}
+
+impl<'a> Scope<'a> {
+    fn get(&self, name: &VarName) -> Option<&'a DynVal> {
+        match self {
+            Scope::Vars(values) => values.get(name),
+            Scope::Binding(_, bound, value) if *bound == name => Some(value),
+            Scope::Binding(parent, ..) => parent.get(name),
+        }
+    }
+
+    fn names(&self) -> Box<dyn Iterator<Item = &'a VarName> + 'a> {
+        match *self {
+            Scope::Vars(values) => Box::new(values.keys()),
+            Scope::Binding(parent, name, _) => Box::new(std::iter::once(name).chain(parent.names())),
+        }
+    }
+}
+
+/// Maximum number of elements generated by `range`, and maximum width `pad_left`, `pad_right` and `format` pad to.
+/// Expressions are evaluated on the main thread, so a typo must not be able to allocate unbounded amounts of memory.
+const MAX_GENERATED_LEN: usize = 100_000;
+
+/// Functions that take a lambda as their second argument.
+const LAMBDA_FUNCTIONS: &[&str] = &["map", "filter", "reduce", "find", "any", "all", "sort_by", "group_by"];
+
+/// Call one of the [`LAMBDA_FUNCTIONS`], which call the given lambda for every element of a JSON array.
+fn call_lambda_function(name: &str, args: &[SimplExpr], scope: Scope, functions: &ExtraFunctions) -> Result<DynVal, EvalError> {
+    use serde_json::Value;
+
+    let expected_params = if name == "reduce" { 2 } else { 1 };
+    let (array, params, body, initial) = match args {
+        [array, SimplExpr::Lambda(_, params, body)] if name != "reduce" => (array, params, body, None),
+        [array, SimplExpr::Lambda(_, params, body), initial] if name == "reduce" => (array, params, body, Some(initial)),
+        [_, SimplExpr::Lambda(..)] | [_, SimplExpr::Lambda(..), _] => return Err(EvalError::WrongArgCount(name.to_string())),
+        _ => return Err(EvalError::ExpectedLambda(name.to_string(), expected_params)),
+    };
+    if params.len() != expected_params {
+        return Err(EvalError::ExpectedLambda(name.to_string(), expected_params));
+    }
+    let items = array.eval_in_scope(scope, functions)?.as_json_array()?;
+
+    // the parameters of the lambda are added to the variables in scope, shadowing any variables of the same name
+    let call = |args: [DynVal; 2]| {
+        let scope = Scope::Binding(&scope, &params[0], &args[0]);
+        match params.get(1) {
+            Some(param) => body.eval_in_scope(Scope::Binding(&scope, param, &args[1]), functions),
+            None => body.eval_in_scope(scope, functions),
+        }
+    };
+    let call_with_item = |item: &Value| call([DynVal::from(item), DynVal::from("")]);
+
+    Ok(match name {
+        "map" => {
+            let results = items.iter().map(|item| Ok(dynval_to_json(call_with_item(item)?))).collect::<Result<_, EvalError>>()?;
+            DynVal::try_from(Value::Array(results))?
+        }
+        "filter" => {
+            let mut results = Vec::new();
+            for item in items {
+                if call_with_item(&item)?.as_bool()? {
+                    results.push(item);
+                }
+            }
+            DynVal::try_from(Value::Array(results))?
+        }
+        "find" => {
+            for item in items {
+                if call_with_item(&item)?.as_bool()? {
+                    return Ok(DynVal::from(&item));
+                }
+            }
+            DynVal::from(&Value::Null)
+        }
+        "any" | "all" => {
+            let mut result = name == "all";
+            for item in items {
+                if call_with_item(&item)?.as_bool()? != result {
+                    result = !result;
+                    break;
+                }
+            }
+            DynVal::from(result)
+        }
+        "reduce" => {
+            let mut accumulator = initial.expect("reduce has an initial value").eval_in_scope(scope, functions)?;
+            for item in items {
+                accumulator = call([accumulator, DynVal::from(&item)])?;
+            }
+            accumulator
+        }
+        "sort_by" => {
+            let keys = items.iter().map(call_with_item).collect::<Result<Vec<_>, EvalError>>()?;
+            // sort numerically if all keys are numbers, and alphabetically otherwise
+            let items = match keys.iter().map(DynVal::as_f64).collect::<Result<Vec<_>, _>>() {
+                Ok(keys) => {
+                    items.into_iter().zip(keys).sorted_by(|(_, a), (_, b)| a.total_cmp(b)).map(|(item, _)| item).collect()
+                }
+                Err(_) => items.into_iter().zip(keys).sorted_by(|(_, a), (_, b)| a.0.cmp(&b.0)).map(|(item, _)| item).collect(),
+            };
+            DynVal::try_from(Value::Array(items))?
+        }
+        "group_by" => {
+            let mut groups = serde_json::Map::new();
+            for item in items {
+                let key = call_with_item(&item)?.as_string()?;
+                match groups.entry(key).or_insert_with(|| Value::Array(Vec::new())) {
+                    Value::Array(group) => group.push(item),
+                    _ => unreachable!(),
+                }
+            }
+            DynVal::try_from(Value::Object(groups))?
+        }
+        _ => unreachable!("not a lambda function: {}", name),
+    })
+}
+
+/// Convert the result of an expression into a JSON value, keeping JSON values as they are, and turning anything else into a string.
+fn dynval_to_json(value: DynVal) -> serde_json::Value {
+    serde_json::from_str(&value.0).unwrap_or(serde_json::Value::String(value.0))
+}
+
 fn call_expr_function(name: &str, args: Vec<DynVal>) -> Result<DynVal, EvalError> {
+    use serde_json::Value;
     match name {
         "get_env" => match args.as_slice() {
             [var_name] => {
@@ -382,6 +582,108 @@ fn call_expr_function(name: &str, args: Vec<DynVal>) -> Result<DynVal, EvalError
             }
             _ => Err(EvalError::WrongArgCount(name.to_string())),
         },
+        "floor" => match args.as_slice() {
+            [num] => Ok(DynVal::from(num.as_f64()?.floor())),
+            _ => Err(EvalError::WrongArgCount(name.to_string())),
+        },
+        "ceil" => match args.as_slice() {
+            [num] => Ok(DynVal::from(num.as_f64()?.ceil())),
+            _ => Err(EvalError::WrongArgCount(name.to_string())),
+        },
+        "abs" => match args.as_slice() {
+            [num] => Ok(DynVal::from(num.as_f64()?.abs())),
+            _ => Err(EvalError::WrongArgCount(name.to_string())),
+        },
+        "pow" => match args.as_slice() {
+            [base, exponent] => Ok(DynVal::from(base.as_f64()?.powf(exponent.as_f64()?))),
+            _ => Err(EvalError::WrongArgCount(name.to_string())),
+        },
+        "sqrt" => match args.as_slice() {
+            [num] => match num.as_f64()? {
+                num if num < 0.0 => Err(EvalError::FunctionFailed(
+                    name.to_string(),
+                    format!("Can't take the square root of negative number {}", num),
+                )),
+                num => Ok(DynVal::from(num.sqrt())),
+            },
+            _ => Err(EvalError::WrongArgCount(name.to_string())),
+        },
+        "log" | "ln" => {
+            let (num, base) = match (name, args.as_slice()) {
+                ("ln", [num]) => (num.as_f64()?, None),
+                ("log", [num]) => (num.as_f64()?, Some(10.0)),
+                ("log", [num, base]) => (num.as_f64()?, Some(base.as_f64()?)),
+                _ => return Err(EvalError::WrongArgCount(name.to_string())),
+            };
+            if num <= 0.0 {
+                return Err(EvalError::FunctionFailed(
+                    name.to_string(),
+                    format!("Can't take the logarithm of non-positive number {}", num),
+                ));
+            }
+            if let Some(base) = base
+                && (base <= 0.0 || base == 1.0 || base.is_nan())
+            {
+                return Err(EvalError::FunctionFailed(
+                    name.to_string(),
+                    format!("The base must be positive and not 1, but is {}", base),
+                ));
+            }
+            // the specialized functions are more precise than `log` for their bases
+            Ok(DynVal::from(match base {
+                None => num.ln(),
+                Some(10.0) => num.log10(),
+                Some(2.0) => num.log2(),
+                Some(base) => num.log(base),
+            }))
+        }
+        "exp" => match args.as_slice() {
+            [num] => Ok(DynVal::from(num.as_f64()?.exp())),
+            _ => Err(EvalError::WrongArgCount(name.to_string())),
+        },
+        "clamp" => match args.as_slice() {
+            [num, min, max] => {
+                let (num, min, max) = (num.as_f64()?, min.as_f64()?, max.as_f64()?);
+                if min > max {
+                    return Err(EvalError::FunctionFailed(
+                        name.to_string(),
+                        format!("Minimum {} is greater than maximum {}", min, max),
+                    ));
+                }
+                Ok(DynVal::from(num.clamp(min, max)))
+            }
+            _ => Err(EvalError::WrongArgCount(name.to_string())),
+        },
+        "lerp" => match args.as_slice() {
+            [a, b, t] => {
+                let (a, b, t) = (a.as_f64()?, b.as_f64()?, t.as_f64()?);
+                Ok(DynVal::from(a + (b - a) * t))
+            }
+            _ => Err(EvalError::WrongArgCount(name.to_string())),
+        },
+        "map_range" => match args.as_slice() {
+            [num, from_min, from_max, to_min, to_max] => {
+                let (num, from_min, from_max) = (num.as_f64()?, from_min.as_f64()?, from_max.as_f64()?);
+                let (to_min, to_max) = (to_min.as_f64()?, to_max.as_f64()?);
+                if from_min == from_max {
+                    return Err(EvalError::FunctionFailed(name.to_string(), "The input range must not be empty".to_string()));
+                }
+                Ok(DynVal::from(to_min + (num - from_min) * (to_max - to_min) / (from_max - from_min)))
+            }
+            _ => Err(EvalError::WrongArgCount(name.to_string())),
+        },
+        "atan2" => match args.as_slice() {
+            [y, x] => Ok(DynVal::from(y.as_f64()?.atan2(x.as_f64()?))),
+            _ => Err(EvalError::WrongArgCount(name.to_string())),
+        },
+        "random" => {
+            let (min, max) = match args.as_slice() {
+                [] => (0.0, 1.0),
+                [min, max] => (min.as_f64()?, max.as_f64()?),
+                _ => return Err(EvalError::WrongArgCount(name.to_string())),
+            };
+            Ok(DynVal::from(min + random_f64() * (max - min)))
+        }
         "matches" => match args.as_slice() {
             [string, pattern] => {
                 let string = string.as_string()?;
@@ -413,7 +715,6 @@ fn call_expr_function(name: &str, args: Vec<DynVal>) -> Result<DynVal, EvalError
         },
         "search" => match args.as_slice() {
             [string, pattern] => {
-                use serde_json::Value;
                 let string = string.as_string()?;
                 let pattern = regex::Regex::new(&pattern.as_string()?)?;
                 Ok(Value::Array(pattern.find_iter(&string).map(|x| Value::String(x.as_str().to_string())).collect())
@@ -423,7 +724,6 @@ fn call_expr_function(name: &str, args: Vec<DynVal>) -> Result<DynVal, EvalError
         },
         "captures" => match args.as_slice() {
             [string, pattern] => {
-                use serde_json::Value;
                 let string = string.as_string()?;
                 let pattern = regex::Regex::new(&pattern.as_string()?)?;
                 Ok(Value::Array(
@@ -450,6 +750,180 @@ fn call_expr_function(name: &str, args: Vec<DynVal>) -> Result<DynVal, EvalError
             [json] => Ok(DynVal::from(json.as_json_object()?.len() as i32)),
             _ => Err(EvalError::WrongArgCount(name.to_string())),
         },
+        "keys" => match args.as_slice() {
+            [json] => {
+                Ok(Value::Array(json.as_json_object()?.into_iter().map(|(key, _)| Value::String(key)).collect()).try_into()?)
+            }
+            _ => Err(EvalError::WrongArgCount(name.to_string())),
+        },
+        "values" => match args.as_slice() {
+            [json] => Ok(Value::Array(json.as_json_object()?.into_iter().map(|(_, value)| value).collect()).try_into()?),
+            _ => Err(EvalError::WrongArgCount(name.to_string())),
+        },
+        "entries" => match args.as_slice() {
+            [json] => Ok(Value::Array(
+                json.as_json_object()?
+                    .into_iter()
+                    .map(|(key, value)| serde_json::json!({ "key": key, "value": value }))
+                    .collect(),
+            )
+            .try_into()?),
+            _ => Err(EvalError::WrongArgCount(name.to_string())),
+        },
+        "merge" => match args.as_slice() {
+            [a, b] => match (a.as_json_value()?, b.as_json_value()?) {
+                (Value::Object(mut a), Value::Object(b)) => {
+                    a.extend(b);
+                    Ok(Value::Object(a).try_into()?)
+                }
+                (Value::Array(mut a), Value::Array(b)) => {
+                    a.extend(b);
+                    Ok(Value::Array(a).try_into()?)
+                }
+                _ => Err(EvalError::FunctionFailed(name.to_string(), "Expected two objects or two arrays".to_string())),
+            },
+            _ => Err(EvalError::WrongArgCount(name.to_string())),
+        },
+        "slice" => {
+            let (value, start, end) = match args.as_slice() {
+                [value, start] => (value, start.as_i64()?, None),
+                [value, start, end] => (value, start.as_i64()?, Some(end.as_i64()?)),
+                _ => return Err(EvalError::WrongArgCount(name.to_string())),
+            };
+            // negative indices count from the end, like in python
+            let range = |len: usize| {
+                let resolve =
+                    |index: i64| if index < 0 { (len as i64 + index).max(0) as usize } else { (index as usize).min(len) };
+                let (start, end) = (resolve(start), end.map(resolve).unwrap_or(len));
+                start..end.max(start)
+            };
+            match value.as_json_array() {
+                Ok(array) => Ok(Value::Array(array[range(array.len())].to_vec()).try_into()?),
+                Err(_) => {
+                    let chars = value.as_string()?.chars().collect::<Vec<_>>();
+                    Ok(DynVal::from(chars[range(chars.len())].iter().collect::<String>()))
+                }
+            }
+        }
+        "split" => match args.as_slice() {
+            [string, separator] => Ok(Value::Array(
+                string.as_string()?.split(&separator.as_string()?).map(|part| Value::String(part.to_string())).collect(),
+            )
+            .try_into()?),
+            _ => Err(EvalError::WrongArgCount(name.to_string())),
+        },
+        "join" => match args.as_slice() {
+            [array, separator] => {
+                let elements = array.as_json_array()?.iter().map(|element| DynVal::from(element).0).collect::<Vec<_>>();
+                Ok(DynVal::from(elements.join(&separator.as_string()?)))
+            }
+            _ => Err(EvalError::WrongArgCount(name.to_string())),
+        },
+        "range" => {
+            let (start, end, step) = match args.as_slice() {
+                [end] => (0, end.as_i64()?, 1),
+                [start, end] => (start.as_i64()?, end.as_i64()?, 1),
+                [start, end, step] => (start.as_i64()?, end.as_i64()?, step.as_i64()?),
+                _ => return Err(EvalError::WrongArgCount(name.to_string())),
+            };
+            if step == 0 {
+                return Err(EvalError::FunctionFailed(name.to_string(), "The step must not be zero".to_string()));
+            }
+            let len = (end as i128 - start as i128 + step as i128 - step.signum() as i128) / step as i128;
+            check_generated_len(name, len.max(0).try_into().unwrap_or(usize::MAX))?;
+            let numbers = std::iter::successors(Some(start), |n| n.checked_add(step))
+                .take_while(|n| if step > 0 { *n < end } else { *n > end })
+                .map(Value::from);
+            Ok(Value::Array(numbers.collect()).try_into()?)
+        }
+        "contains" => match args.as_slice() {
+            [haystack, needle] => Ok(DynVal::from(match haystack.as_json_value() {
+                Ok(Value::Array(array)) => array.iter().any(|element| DynVal::from(element) == *needle),
+                Ok(Value::Object(object)) => object.contains_key(&needle.as_string()?),
+                _ => haystack.as_string()?.contains(&needle.as_string()?),
+            })),
+            _ => Err(EvalError::WrongArgCount(name.to_string())),
+        },
+        "index_of" => match args.as_slice() {
+            [haystack, needle] => {
+                let index = match haystack.as_json_array() {
+                    Ok(array) => array.iter().position(|element| DynVal::from(element) == *needle),
+                    Err(_) => {
+                        let haystack = haystack.as_string()?;
+                        haystack.find(&needle.as_string()?).map(|byte_index| haystack[..byte_index].chars().count())
+                    }
+                };
+                Ok(DynVal::from(index.map(|index| index as i32).unwrap_or(-1)))
+            }
+            _ => Err(EvalError::WrongArgCount(name.to_string())),
+        },
+        "unique" => match args.as_slice() {
+            [array] => {
+                let mut unique = Vec::new();
+                for element in array.as_json_array()? {
+                    if !unique.contains(&element) {
+                        unique.push(element);
+                    }
+                }
+                Ok(Value::Array(unique).try_into()?)
+            }
+            _ => Err(EvalError::WrongArgCount(name.to_string())),
+        },
+        "reverse" => match args.as_slice() {
+            [value] => match value.as_json_array() {
+                Ok(array) => Ok(Value::Array(array.into_iter().rev().collect()).try_into()?),
+                Err(_) => Ok(DynVal::from(value.as_string()?.chars().rev().collect::<String>())),
+            },
+            _ => Err(EvalError::WrongArgCount(name.to_string())),
+        },
+        "sum" | "avg" => match args.as_slice() {
+            [array] => {
+                let numbers =
+                    array.as_json_array()?.iter().map(|element| DynVal::from(element).as_f64()).collect::<Result<Vec<_>, _>>()?;
+                let sum: f64 = numbers.iter().sum();
+                if name == "sum" {
+                    Ok(DynVal::from(sum))
+                } else if numbers.is_empty() {
+                    Err(EvalError::FunctionFailed(name.to_string(), "Can't average an empty array".to_string()))
+                } else {
+                    Ok(DynVal::from(sum / numbers.len() as f64))
+                }
+            }
+            _ => Err(EvalError::WrongArgCount(name.to_string())),
+        },
+        "typeof" => match args.as_slice() {
+            [value] => Ok(DynVal::from(match dynval_to_json(value.clone()) {
+                Value::Null => "null",
+                Value::Bool(_) => "bool",
+                Value::Number(_) => "number",
+                Value::String(_) => "string",
+                Value::Array(_) => "array",
+                Value::Object(_) => "object",
+            })),
+            _ => Err(EvalError::WrongArgCount(name.to_string())),
+        },
+        "to_number" => match args.as_slice() {
+            [value] => Ok(DynVal::from(
+                value
+                    .as_string()?
+                    .trim()
+                    .parse::<f64>()
+                    .map_err(|_| EvalError::FunctionFailed(name.to_string(), format!("`{}` is not a number", value)))?,
+            )),
+            _ => Err(EvalError::WrongArgCount(name.to_string())),
+        },
+        "to_json" => match args.as_slice() {
+            [value] => Ok(DynVal::from(dynval_to_json(value.clone()).to_string())),
+            _ => Err(EvalError::WrongArgCount(name.to_string())),
+        },
+        "from_json" => match args.as_slice() {
+            [string] => Ok(DynVal::from(&serde_json::from_str::<Value>(&string.as_string()?)?)),
+            _ => Err(EvalError::WrongArgCount(name.to_string())),
+        },
+        "default" => match args.as_slice() {
+            [value, fallback] => Ok(if value.0.is_empty() || value.0 == "null" { fallback.clone() } else { value.clone() }),
+            _ => Err(EvalError::WrongArgCount(name.to_string())),
+        },
         "jq" => match args.as_slice() {
             [json, code] => run_jaq_function(json.as_json_value()?, code.as_string()?)
                 .map_err(|e| EvalError::Spanned(code.span(), Box::new(e))),
@@ -477,11 +951,211 @@ fn call_expr_function(name: &str, args: Vec<DynVal>) -> Result<DynVal, EvalError
             })),
             _ => Err(EvalError::WrongArgCount(name.to_string())),
         },
+        "format" => match args.as_slice() {
+            [format, values @ ..] => Ok(DynVal::from(format_values(&format.as_string()?, values)?)),
+            _ => Err(EvalError::WrongArgCount(name.to_string())),
+        },
+        "pad_left" | "pad_right" => {
+            let (string, width, fill) = match args.as_slice() {
+                [string, width] => (string.as_string()?, width.as_i32()?, ' '),
+                [string, width, fill] => (string.as_string()?, width.as_i32()?, single_char(name, fill)?),
+                _ => return Err(EvalError::WrongArgCount(name.to_string())),
+            };
+            let align = if name == "pad_left" { '>' } else { '<' };
+            Ok(DynVal::from(pad(&string, check_generated_len(name, width.max(0) as usize)?, fill, align)))
+        }
+        "truncate" => {
+            let (string, length, ellipsis) = match args.as_slice() {
+                [string, length] => (string.as_string()?, length.as_i32()?, "…".to_string()),
+                [string, length, ellipsis] => (string.as_string()?, length.as_i32()?, ellipsis.as_string()?),
+                _ => return Err(EvalError::WrongArgCount(name.to_string())),
+            };
+            let length = length.max(0) as usize;
+            if string.chars().count() <= length {
+                return Ok(DynVal::from(string));
+            }
+            let kept = length.saturating_sub(ellipsis.chars().count());
+            Ok(DynVal::from(format!("{}{}", string.chars().take(kept).collect::<String>(), ellipsis)))
+        }
+        "humanize_bytes" => {
+            let (bytes, system) = match args.as_slice() {
+                [bytes] => (bytes.as_f64()?, "iec".to_string()),
+                [bytes, system] => (bytes.as_f64()?, system.as_string()?),
+                _ => return Err(EvalError::WrongArgCount(name.to_string())),
+            };
+            let (base, units) = match system.as_str() {
+                "iec" => (1024.0, ["B", "KiB", "MiB", "GiB", "TiB", "PiB", "EiB"]),
+                "si" => (1000.0, ["B", "kB", "MB", "GB", "TB", "PB", "EB"]),
+                _ => {
+                    return Err(EvalError::FunctionFailed(
+                        name.to_string(),
+                        format!("Unknown unit system {}, expected iec or si", system),
+                    ));
+                }
+            };
+            let mut value = bytes;
+            let mut unit = 0;
+            while value.abs() >= base && unit < units.len() - 1 {
+                value /= base;
+                unit += 1;
+            }
+            Ok(DynVal::from(if unit == 0 { format!("{} {}", value, units[0]) } else { format!("{:.1} {}", value, units[unit]) }))
+        }
+        "humanize_duration" => match args.as_slice() {
+            [seconds] => {
+                let seconds = seconds.as_f64()?.max(0.0) as u64;
+                let parts = [(seconds / 86400, "d"), (seconds / 3600 % 24, "h"), (seconds / 60 % 60, "m"), (seconds % 60, "s")];
+                // only the two largest units are shown, starting at the first one that isn't zero
+                let shown = parts.iter().skip_while(|(amount, _)| *amount == 0).take(2).filter(|(amount, _)| *amount != 0);
+                let result = shown.map(|(amount, unit)| format!("{}{}", amount, unit)).join(" ");
+                Ok(DynVal::from(if result.is_empty() { "0s".to_string() } else { result }))
+            }
+            _ => Err(EvalError::WrongArgCount(name.to_string())),
+        },
 
         _ => Err(EvalError::UnknownFunction(name.to_string())),
     }
 }
 
+/// Get a random number in `[0, 1)` from a xorshift64* generator. It is seeded once per thread from the randomly seeded
+/// hasher of the standard library, which avoids a dependency on `rand`.
+fn random_f64() -> f64 {
+    thread_local! {
+        static STATE: std::cell::Cell<u64> = {
+            use std::hash::{BuildHasher, Hasher};
+            // the state must never be zero
+            std::cell::Cell::new(std::collections::hash_map::RandomState::new().build_hasher().finish() | 1)
+        };
+    }
+    let random = STATE.with(|state| {
+        let mut x = state.get();
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        state.set(x);
+        x.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    });
+    // the upper 53 bits fit into the mantissa of an f64 exactly
+    (random >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Check that a length or width requested in an expression doesn't exceed [`MAX_GENERATED_LEN`].
+fn check_generated_len(function_name: &str, len: usize) -> Result<usize, EvalError> {
+    if len > MAX_GENERATED_LEN {
+        return Err(EvalError::FunctionFailed(
+            function_name.to_string(),
+            format!("The result would be {} long, which exceeds the maximum of {}", len, MAX_GENERATED_LEN),
+        ));
+    }
+    Ok(len)
+}
+
+fn single_char(function_name: &str, value: &DynVal) -> Result<char, EvalError> {
+    let string = value.as_string()?;
+    let mut chars = string.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Ok(c),
+        _ => Err(EvalError::FunctionFailed(function_name.to_string(), format!("Expected a single character, got `{}`", string))),
+    }
+}
+
+/// Pad `string` with `fill` to `width` characters, aligning it to the left (`<`), right (`>`) or center (`^`).
+fn pad(string: &str, width: usize, fill: char, align: char) -> String {
+    let padding = width.saturating_sub(string.chars().count());
+    let (left, right) = match align {
+        '<' => (0, padding),
+        '^' => (padding / 2, padding - padding / 2),
+        _ => (padding, 0),
+    };
+    let fill = |n| std::iter::repeat_n(fill, n).collect::<String>();
+    format!("{}{}{}", fill(left), string, fill(right))
+}
+
+/// Format `values` like rusts `format!`: `{}` is replaced by the next value, `{1}` by the second one.
+/// After a colon, a format spec of the form `[[fill]align][+][0][width][.precision]` can be given, like `{:>5.1}`.
+/// The precision is the number of decimals of numbers, and the maximum length of anything else.
+fn format_values(format: &str, values: &[DynVal]) -> Result<String, EvalError> {
+    let error = |message: String| EvalError::FunctionFailed("format".to_string(), message);
+    let mut output = String::new();
+    let mut next_value = 0;
+    let mut rest = format;
+    while let Some(start) = rest.find(['{', '}']) {
+        output.push_str(&rest[..start]);
+        let (brace, after) = rest[start..].split_at(1);
+        if let Some(after) = after.strip_prefix(brace) {
+            output.push_str(brace);
+            rest = after;
+            continue;
+        }
+        if brace == "}" {
+            return Err(error("Unmatched `}` in format string, use `}}` to output a `}`".to_string()));
+        }
+        let end = after.find('}').ok_or_else(|| error("Unclosed `{` in format string".to_string()))?;
+        let (index, spec) = after[..end].split_once(':').unwrap_or((&after[..end], ""));
+        let index = if index.is_empty() {
+            next_value += 1;
+            next_value - 1
+        } else {
+            index.parse().map_err(|_| error(format!("Invalid argument index `{}`", index)))?
+        };
+        let value = values.get(index).ok_or_else(|| error(format!("Missing argument {} for format string", index)))?;
+        output.push_str(&format_value(value, spec)?);
+        rest = &after[end + 1..];
+    }
+    output.push_str(rest);
+    Ok(output)
+}
+
+/// Format a single value according to a format spec, see [`format_values`].
+fn format_value(value: &DynVal, spec: &str) -> Result<String, EvalError> {
+    let invalid_spec = || EvalError::FunctionFailed("format".to_string(), format!("Invalid format spec `{}`", spec));
+    let mut chars = spec.chars().peekable();
+    let is_align = |c: char| matches!(c, '<' | '>' | '^');
+    let (fill, align) = match (spec.chars().next(), spec.chars().nth(1)) {
+        (Some(fill), Some(align)) if is_align(align) => {
+            chars.nth(1);
+            (fill, Some(align))
+        }
+        (Some(align), _) if is_align(align) => {
+            chars.next();
+            (' ', Some(align))
+        }
+        _ => (' ', None),
+    };
+    let plus = chars.next_if_eq(&'+').is_some();
+    let zero = chars.next_if_eq(&'0').is_some();
+    let rest: String = chars.collect();
+    let (width, precision) = match rest.split_once('.') {
+        Some((width, precision)) => (width, Some(precision.parse::<usize>().map_err(|_| invalid_spec())?)),
+        None => (rest.as_str(), None),
+    };
+    let width = if width.is_empty() { 0 } else { width.parse::<usize>().map_err(|_| invalid_spec())? };
+    check_generated_len("format", width)?;
+    if let Some(precision) = precision {
+        check_generated_len("format", precision)?;
+    }
+
+    let number = value.as_f64().ok();
+    let mut formatted = match (number, precision) {
+        (Some(number), Some(precision)) => format!("{:.*}", precision, number),
+        (None, Some(precision)) => value.0.chars().take(precision).collect(),
+        _ => value.0.clone(),
+    };
+    if plus && number.is_some_and(|number| number >= 0.0) {
+        formatted.insert(0, '+');
+    }
+    Ok(match (number, zero, align) {
+        // zeros are added after the sign, like in rust
+        (Some(_), true, None) => {
+            let sign_len = if formatted.starts_with(['+', '-']) { 1 } else { 0 };
+            let (sign, digits) = formatted.split_at(sign_len);
+            format!("{}{}", sign, pad(digits, width.saturating_sub(sign_len), '0', '>'))
+        }
+        (Some(_), _, None) => pad(&formatted, width, fill, '>'),
+        (_, _, align) => pad(&formatted, width, fill, align.unwrap_or('<')),
+    })
+}
+
 use jaq_core::{Ctx, RcIter, compile, load};
 use jaq_json::Val;
 
@@ -569,42 +1243,39 @@ mod tests {
     use crate::dynval::DynVal;
 
     macro_rules! evals_as {
-        ($name:ident($simplexpr:expr) => $expected:expr $(,)?) => {
-            #[test]
-            fn $name() {
-                let expected: Result<$crate::dynval::DynVal, $crate::eval::EvalError> = $expected;
-
-                let parsed = match $crate::parser::parse_string(0, 0, $simplexpr.into()) {
-                    Ok(it) => it,
-                    Err(e) => {
-                        panic!("Could not parse input as SimpleExpr\nInput: {}\nReason: {}", stringify!($simplexpr), e);
-                    }
-                };
+        ($($name:ident($simplexpr:expr) => $expected:expr),* $(,)?) => {
+            $(
+                #[test]
+                fn $name() {
+                    let expected: Result<$crate::dynval::DynVal, $crate::eval::EvalError> = $expected;
 
-                eprintln!("Parsed as {parsed:#?}");
+                    let parsed = match $crate::parser::parse_string(0, 0, $simplexpr.into()) {
+                        Ok(it) => it,
+                        Err(e) => {
+                            panic!("Could not parse input as SimpleExpr\nInput: {}\nReason: {}", stringify!($simplexpr), e);
+                        }
+                    };
 
-                let output = parsed.eval_no_vars();
+                    eprintln!("Parsed as {parsed:#?}");
 
-                match expected {
-                    Ok(expected) => {
-                        let actual = output.expect("Output was not Ok(_)");
+                    let output = parsed.eval_no_vars();
 
-                        assert_eq!(expected, actual);
-                    }
-                    Err(expected) => {
-                        let actual = output.expect_err("Output was not Err(_)").to_string();
-                        let expected = expected.to_string();
+                    match expected {
+                        Ok(expected) => {
+                            let actual = output.expect("Output was not Ok(_)");
 
-                        assert_eq!(expected, actual);
+                            assert_eq!(expected, actual);
+                        }
+                        Err(expected) => {
+                            let actual = output.expect_err("Output was not Err(_)").to_string();
+                            let expected = expected.to_string();
+
+                            assert_eq!(expected, actual);
+                        }
                     }
                 }
-            }
+            )*
         };
-
-        ($name:ident($simplexpr:expr) => $expected:expr, $($tt:tt)+) => {
-            evals_as!($name($simplexpr) => $expected);
-            evals_as!($($tt)*);
-        }
     }
 
     evals_as! {
@@ -622,5 +1293,186 @@ mod tests {
         lazy_evaluation_or(r#"true || "null".test"#) => Ok(DynVal::from(true)),
         lazy_evaluation_elvis(r#""test"?: "null".test"#) => Ok(DynVal::from("test")),
         jq_basic_index(r#"jq("[7,8,9]", ".[0]")"#) => Ok(DynVal::from(7)),
+        lambda_map(r#"map([{"name": "a"}, {"name": "b"}], x => x.name)"#) => Ok(DynVal::from(r#"["a","b"]"#)),
+        lambda_map_objects(r#"map([1, 2], x => {"double": x * 2})"#) => Ok(DynVal::from(r#"[{"double":"2"},{"double":"4"}]"#)),
+        lambda_filter(r#"filter([1, 2, 3, 4], x => x % 2 == 0)"#) => Ok(DynVal::from(r#"["2","4"]"#)),
+        lambda_reduce(r#"reduce([1, 2, 3], (sum, x) => sum + x, 10)"#) => Ok(DynVal::from(16)),
+        lambda_find(r#"find([{"id": 1}, {"id": 2}], x => x.id == 2).id"#) => Ok(DynVal::from(2)),
+        lambda_find_missing(r#"find([1, 2], x => x > 2)"#) => Ok(DynVal::from(&serde_json::Value::Null)),
+        lambda_any(r#"any([1, 2], x => x > 1)"#) => Ok(DynVal::from(true)),
+        lambda_all(r#"all([1, 2], x => x > 1)"#) => Ok(DynVal::from(false)),
+        lambda_all_empty(r#"all([], x => false)"#) => Ok(DynVal::from(true)),
+        lambda_sort_by_number(r#"sort_by([10, 9, 100], x => x)"#) => Ok(DynVal::from(r#"["9","10","100"]"#)),
+        lambda_sort_by_string(r#"sort_by(["b", "c", "a"], x => x)"#) => Ok(DynVal::from(r#"["a","b","c"]"#)),
+        lambda_group_by(r#"group_by([1, 2, 3], x => x % 2 == 0 ? "even" : "odd")"#) => Ok(DynVal::from(r#"{"even":["2"],"odd":["1","3"]}"#)),
+        lambda_nested(r#"map([[1, 2], [3]], xs => map(xs, x => x * 10))"#) => Ok(DynVal::from("[[10,20],[30]]")),
+        lambda_wrong_params(r#"reduce([1], x => x, 0)"#) => Err(super::EvalError::ExpectedLambda("reduce".to_string(), 2)),
+        lambda_missing(r#"map([1], 2)"#) => Err(super::EvalError::ExpectedLambda("map".to_string(), 1)),
+        lambda_outside_function(r#"round(x => x, 1)"#) => Err(super::EvalError::UnexpectedLambda),
+        let_basic(r#"let x = 2 in x * x"#) => Ok(DynVal::from(4)),
+        let_nested(r#"let x = 2 in let y = x + 1 in x * y"#) => Ok(DynVal::from(6)),
+        let_shadowing(r#"let x = 1 in (let x = x + 1 in x) + x"#) => Ok(DynVal::from(3)),
+        let_json(r#"let bat = { "capacity": 80 } in bat.capacity > 20 ? "${bat.capacity}%" : "low""#) => Ok(DynVal::from("80%")),
+        let_lowest_precedence(r#"let x = 1 in x == 1 ? "yes" : "no""#) => Ok(DynVal::from("yes")),
+        format_basic(r#"format("{} of {}", 1, 2)"#) => Ok(DynVal::from("1 of 2")),
+        format_spec(r#"format("{:>5.1}%", 42.123)"#) => Ok(DynVal::from(" 42.1%")),
+        format_fill(r#"format("[{:*^7}] [{:<4}] [{:3}]", "ab", "x", 7)"#) => Ok(DynVal::from("[**ab***] [x   ] [  7]")),
+        format_zero_and_sign(r#"format("{:+06.2} {:03}", 3.14159, -5)"#) => Ok(DynVal::from("+03.14 -05")),
+        format_index(r#"format("{1}{0}{{}}", "a", "b")"#) => Ok(DynVal::from("ba{}")),
+        format_string_precision(r#"format("{:.3}", "abcdef")"#) => Ok(DynVal::from("abc")),
+        format_missing_arg(r#"format("{} {}", 1)"#) => Err(super::EvalError::FunctionFailed("format".to_string(), "Missing argument 1 for format string".to_string())),
+        format_invalid_spec(r#"format("{:x}", 1)"#) => Err(super::EvalError::FunctionFailed("format".to_string(), "Invalid format spec `x`".to_string())),
+        pad_left(r#"pad_left("5", 3)"#) => Ok(DynVal::from("  5")),
+        pad_right(r#"pad_right("ab", 4, ".")"#) => Ok(DynVal::from("ab..")),
+        pad_shorter(r#"pad_left("abc", 2)"#) => Ok(DynVal::from("abc")),
+        pad_too_wide(r#"pad_right("abc", 2147483647)"#) => Err(super::EvalError::FunctionFailed("pad_right".to_string(), "The result would be 2147483647 long, which exceeds the maximum of 100000".to_string())),
+        format_too_wide(r#"format("{:1000000}", 1)"#) => Err(super::EvalError::FunctionFailed("format".to_string(), "The result would be 1000000 long, which exceeds the maximum of 100000".to_string())),
+        format_too_precise(r#"format("{:.1000000}", 1)"#) => Err(super::EvalError::FunctionFailed("format".to_string(), "The result would be 1000000 long, which exceeds the maximum of 100000".to_string())),
+        truncate_long(r#"truncate("hello world", 6)"#) => Ok(DynVal::from("hello…")),
+        truncate_custom(r#"truncate("hello world", 8, "...")"#) => Ok(DynVal::from("hello...")),
+        truncate_short(r#"truncate("hello", 6)"#) => Ok(DynVal::from("hello")),
+        humanize_bytes_iec(r#"humanize_bytes(1536)"#) => Ok(DynVal::from("1.5 KiB")),
+        humanize_bytes_si(r#"humanize_bytes(1500000, "si")"#) => Ok(DynVal::from("1.5 MB")),
+        humanize_bytes_small(r#"humanize_bytes(512, "iec")"#) => Ok(DynVal::from("512 B")),
+        humanize_bytes_invalid(r#"humanize_bytes(1, "x")"#) => Err(super::EvalError::FunctionFailed("humanize_bytes".to_string(), "Unknown unit system x, expected iec or si".to_string())),
+        humanize_duration_days(r#"humanize_duration(90061)"#) => Ok(DynVal::from("1d 1h")),
+        humanize_duration_skip_zero(r#"humanize_duration(3605)"#) => Ok(DynVal::from("1h")),
+        humanize_duration_seconds(r#"humanize_duration(42)"#) => Ok(DynVal::from("42s")),
+        humanize_duration_zero(r#"humanize_duration(0)"#) => Ok(DynVal::from("0s")),
+        keys(r#"keys({"a": 1, "b": 2})"#) => Ok(DynVal::from(r#"["a","b"]"#)),
+        values(r#"values({"a": 1, "b": "x"})"#) => Ok(DynVal::from(r#"["1","x"]"#)),
+        entries(r#"entries({"a": 1})"#) => Ok(DynVal::from(r#"[{"key":"a","value":"1"}]"#)),
+        merge_objects(r#"merge({"a": 1, "b": 2}, {"b": 3})"#) => Ok(DynVal::from(r#"{"a":"1","b":"3"}"#)),
+        merge_arrays(r#"merge([1], [2, 3])"#) => Ok(DynVal::from(r#"["1","2","3"]"#)),
+        merge_invalid(r#"merge({"a": 1}, [1])"#) => Err(super::EvalError::FunctionFailed("merge".to_string(), "Expected two objects or two arrays".to_string())),
+        slice_array(r#"slice([1, 2, 3, 4], 1, 3)"#) => Ok(DynVal::from(r#"["2","3"]"#)),
+        slice_negative(r#"slice(range(5), -2)"#) => Ok(DynVal::from("[3,4]")),
+        slice_string(r#"slice("hello", 1, -1)"#) => Ok(DynVal::from("ell")),
+        slice_out_of_bounds(r#"slice([1, 2], 5)"#) => Ok(DynVal::from("[]")),
+        split(r#"split("a,b,c", ",")"#) => Ok(DynVal::from(r#"["a","b","c"]"#)),
+        join(r#"join(["a", 1, true], ", ")"#) => Ok(DynVal::from("a, 1, true")),
+        range_end(r#"range(3)"#) => Ok(DynVal::from("[0,1,2]")),
+        range_step(r#"range(10, 0, -4)"#) => Ok(DynVal::from("[10,6,2]")),
+        range_zero_step(r#"range(0, 1, 0)"#) => Err(super::EvalError::FunctionFailed("range".to_string(), "The step must not be zero".to_string())),
+        range_max_len(r#"arraylength(range(-100000, 100000, 2))"#) => Ok(DynVal::from(100000)),
+        range_too_long(r#"range(0, 1000000000000)"#) => Err(super::EvalError::FunctionFailed("range".to_string(), "The result would be 1000000000000 long, which exceeds the maximum of 100000".to_string())),
+        contains_array(r#"contains([1, 2, 3], 2)"#) => Ok(DynVal::from(true)),
+        contains_object(r#"contains({"a": 1}, "b")"#) => Ok(DynVal::from(false)),
+        contains_string(r#"contains("hello", "ell")"#) => Ok(DynVal::from(true)),
+        index_of_array(r#"index_of(["a", "b"], "b")"#) => Ok(DynVal::from(1)),
+        index_of_string(r#"index_of("äbc", "c")"#) => Ok(DynVal::from(2)),
+        index_of_missing(r#"index_of([1, 2], 3)"#) => Ok(DynVal::from(-1)),
+        unique(r#"unique([1, 2, 1, "a", "a"])"#) => Ok(DynVal::from(r#"["1","2","a"]"#)),
+        reverse_array(r#"reverse(range(3))"#) => Ok(DynVal::from("[2,1,0]")),
+        reverse_string(r#"reverse("abc")"#) => Ok(DynVal::from("cba")),
+        sum(r#"sum([1, 2, "3.5"])"#) => Ok(DynVal::from(6.5)),
+        avg(r#"avg([1, 2, 3, 4])"#) => Ok(DynVal::from(2.5)),
+        avg_empty(r#"avg([])"#) => Err(super::EvalError::FunctionFailed("avg".to_string(), "Can't average an empty array".to_string())),
+        typeof_number(r#"typeof(1.5)"#) => Ok(DynVal::from("number")),
+        typeof_string(r#"typeof("hi")"#) => Ok(DynVal::from("string")),
+        typeof_object(r#"typeof({"a": 1})"#) => Ok(DynVal::from("object")),
+        to_number(r#"to_number(" 42 ") + 1"#) => Ok(DynVal::from(43)),
+        to_number_invalid(r#"to_number("abc")"#) => Err(super::EvalError::FunctionFailed("to_number".to_string(), "`abc` is not a number".to_string())),
+        to_json_string(r#"to_json("a\"b")"#) => Ok(DynVal::from(r#""a\"b""#)),
+        to_json_object(r#"to_json({"a": true})"#) => Ok(DynVal::from(r#"{"a":"true"}"#)),
+        from_json(r#"from_json("{\"a\": 1}").a"#) => Ok(DynVal::from(1)),
+        from_json_string(r#"from_json("\"hi\"")"#) => Ok(DynVal::from("hi")),
+        default_empty(r#"default("", "fallback")"#) => Ok(DynVal::from("fallback")),
+        default_null(r#"default(from_json("null"), 1)"#) => Ok(DynVal::from(1)),
+        default_value(r#"default(0, 1)"#) => Ok(DynVal::from(0)),
+        int_div(r#"7 // 2"#) => Ok(DynVal::from(3)),
+        int_div_negative(r#"-7 // 2"#) => Ok(DynVal::from(-4)),
+        int_div_negative_divisor(r#"7 // -2"#) => Ok(DynVal::from(-4)),
+        int_div_both_negative(r#"-7 // -2"#) => Ok(DynVal::from(3)),
+        int_div_negative_exact(r#"-8 // 2"#) => Ok(DynVal::from(-4)),
+        int_div_negative_float(r#"-7.5 // 2"#) => Ok(DynVal::from(-4)),
+        int_div_by_zero(r#"1 // 0"#) => Err(super::EvalError::DivisionByZero),
+        int_div_by_negative_zero(r#"-1 // -0"#) => Err(super::EvalError::DivisionByZero),
+        int_div_precedence(r#"1 + 9 // 2 * 2"#) => Ok(DynVal::from(9)),
+        floor(r#"floor(2.7)"#) => Ok(DynVal::from(2)),
+        ceil(r#"ceil(2.1)"#) => Ok(DynVal::from(3)),
+        abs(r#"abs(-2.5)"#) => Ok(DynVal::from(2.5)),
+        pow(r#"pow(2, 10)"#) => Ok(DynVal::from(1024)),
+        sqrt(r#"sqrt(16)"#) => Ok(DynVal::from(4)),
+        sqrt_negative(r#"sqrt(-1)"#) => Err(super::EvalError::FunctionFailed("sqrt".to_string(), "Can't take the square root of negative number -1".to_string())),
+        log(r#"log(1000)"#) => Ok(DynVal::from(3)),
+        log_base(r#"log(8, 2)"#) => Ok(DynVal::from(3)),
+        ln(r#"ln(exp(2))"#) => Ok(DynVal::from(2)),
+        log_zero(r#"ln(0)"#) => Err(super::EvalError::FunctionFailed("ln".to_string(), "Can't take the logarithm of non-positive number 0".to_string())),
+        log_base_one(r#"log(8, 1)"#) => Err(super::EvalError::FunctionFailed("log".to_string(), "The base must be positive and not 1, but is 1".to_string())),
+        log_base_negative(r#"log(8, -2)"#) => Err(super::EvalError::FunctionFailed("log".to_string(), "The base must be positive and not 1, but is -2".to_string())),
+        log_base_zero(r#"log(8, 0)"#) => Err(super::EvalError::FunctionFailed("log".to_string(), "The base must be positive and not 1, but is 0".to_string())),
+        clamp(r#"clamp(150, 0, 100)"#) => Ok(DynVal::from(100)),
+        clamp_invalid(r#"clamp(1, 2, 0)"#) => Err(super::EvalError::FunctionFailed("clamp".to_string(), "Minimum 2 is greater than maximum 0".to_string())),
+        lerp(r#"lerp(10, 20, 0.25)"#) => Ok(DynVal::from(12.5)),
+        map_range(r#"map_range(50, 0, 100, -135, 135)"#) => Ok(DynVal::from(0)),
+        map_range_empty(r#"map_range(1, 5, 5, 0, 1)"#) => Err(super::EvalError::FunctionFailed("map_range".to_string(), "The input range must not be empty".to_string())),
+        atan2(r#"round(radtodeg(atan2(1, 1)), 0)"#) => Ok(DynVal::from(45)),
+        random_range(r#"random(5, 6) >= 5 && random(5, 6) < 6 && random() < 1"#) => Ok(DynVal::from(true)),
+        let_not_a_keyword_in_identifiers(r#"{ "index": 1, "letter": 2 }.index"#) => Ok(DynVal::from(1)),
+        let_in_progress(r#"let in-progress = 2 in in-progress * 2"#) => Ok(DynVal::from(4)),
+        let_letter_x(r#"let letter-x = 3 in let let-me = letter-x + 1 in let-me"#) => Ok(DynVal::from(4)),
+        let_keywords_as_fields(r#"let obj = { "in": 1, "let": 2 } in obj.in + obj?.let"#) => Ok(DynVal::from(3)),
+        let_shadowing_lambda(r#"let x = 1 in map([10], x => x + 1)[0] + x"#) => Ok(DynVal::from(12)),
+    }
+
+    #[test]
+    fn int_div_by_zero_span() {
+        let err = crate::parser::parse_string(0, 0, "2 + 1 // 0").unwrap().eval_no_vars().unwrap_err();
+        assert_eq!(eww_shared_util::Spanned::span(&err), eww_shared_util::Span(4, 10, 0));
+    }
+
+    #[test]
+    fn random_distribution() {
+        let mut buckets = [0; 10];
+        for _ in 0..10000 {
+            let random = super::random_f64();
+            assert!((0.0..1.0).contains(&random));
+            buckets[(random * 10.0) as usize] += 1;
+        }
+        assert!(buckets.iter().all(|count| (800..1200).contains(count)), "{:?}", buckets);
+    }
+
+    #[test]
+    fn let_scope() {
+        let expr = crate::parser::parse_string(0, 0, "let x = y + 1 in x * z").unwrap();
+        assert_eq!(expr.collect_var_refs(), vec!["y".into(), "z".into()]);
+        assert_eq!(expr.var_refs_with_span().len(), 2);
+        assert!(!expr.references_var(&"x".into()));
+        assert!(crate::parser::parse_string(0, 0, "let x = x in 1").unwrap().references_var(&"x".into()));
+
+        let values = std::collections::HashMap::from([("x".into(), DynVal::from(100)), ("y".into(), DynVal::from(1))]);
+        let expr = crate::parser::parse_string(0, 0, "let x = y + 1 in x + 1").unwrap();
+        assert_eq!(expr.clone().resolve_refs(&values).unwrap().eval_no_vars().unwrap(), DynVal::from(3));
+        assert_eq!(expr.eval(&values).unwrap(), DynVal::from(3));
+
+        // variables bound by `let` are suggested for typos, just like the other variables
+        let err = crate::parser::parse_string(0, 0, "let value = 1 in valeu").unwrap().eval(&values).unwrap_err();
+        let super::EvalError::Spanned(_, err) = err else { panic!("{:?}", err) };
+        assert!(matches!(*err, super::EvalError::UnknownVariable(_, similar) if similar == vec!["value".into()]));
+    }
+
+    #[test]
+    fn lambda_scope() {
+        let expr = crate::parser::parse_string(0, 0, "map(items, x => x + offset) + x").unwrap();
+        assert_eq!(expr.collect_var_refs(), vec!["items".into(), "offset".into(), "x".into()]);
+        assert_eq!(expr.var_refs_with_span().len(), 3);
+        assert!(!crate::parser::parse_string(0, 0, "map(items, x => x)").unwrap().references_var(&"x".into()));
+
+        let values = std::collections::HashMap::from([("items".into(), DynVal::from("[1, 2]")), ("x".into(), DynVal::from(5))]);
+        let resolved =
+            expr.resolve_refs(&values.clone().into_iter().chain([("offset".into(), DynVal::from(1))]).collect()).unwrap();
+        assert_eq!(resolved.eval_no_vars().unwrap(), DynVal::from("[2,3]5"));
+    }
+
+    #[test]
+    fn extra_functions() {
+        let functions = |name: &str, args: &[DynVal]| match name {
+            "double" => Some(args[0].as_f64().map(|x| DynVal::from(x * 2.0)).map_err(Into::into)),
+            _ => None,
+        };
+        let expr = crate::parser::parse_string(0, 0, "double(2) + round(1.25, 1)").unwrap();
+        assert_eq!(expr.eval_with_functions(&std::collections::HashMap::new(), &functions).unwrap(), DynVal::from(5.2));
+        assert!(expr.eval_no_vars().is_err());
     }
 }
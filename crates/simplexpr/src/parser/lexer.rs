@@ -16,6 +16,7 @@ pub enum Token {
     Minus,
     Times,
     Div,
+    IntDiv,
     Mod,
     Equals,
     NotEquals,
@@ -28,6 +29,8 @@ pub enum Token {
     Elvis,
     SafeAccess,
     RegexMatch,
+    Arrow,
+    Assign,
 
     Not,
     Negative,
@@ -44,6 +47,8 @@ pub enum Token {
     Dot,
     True,
     False,
+    Let,
+    In,
 
     Ident(String),
     NumLit(String),
@@ -76,6 +81,7 @@ regex_rules! {
     r"\+"     => |_| Token::Plus,
     r"-"     => |_| Token::Minus,
     r"\*"     => |_| Token::Times,
+    r"//"    => |_| Token::IntDiv,
     r"/"     => |_| Token::Div,
     r"%"     => |_| Token::Mod,
     r"=="    => |_| Token::Equals,
@@ -89,6 +95,8 @@ regex_rules! {
     r"\?:"    => |_| Token::Elvis,
     r"\?\."    => |_| Token::SafeAccess,
     r"=~"    => |_| Token::RegexMatch,
+    r"=>"    => |_| Token::Arrow,
+    r"="     => |_| Token::Assign,
 
     r"!"     => |_| Token::Not,
     r"-"     => |_| Token::Negative,
@@ -109,7 +117,12 @@ regex_rules! {
     r"\s+" => |_| Token::Skip,
     r";.*"=> |_| Token::Comment,
 
-    r"[a-zA-Z_][a-zA-Z0-9_-]*" => Token::Ident,
+    // keywords are only recognized as whole identifiers, so `in-progress` or `letter` stay identifiers
+    r"[a-zA-Z_][a-zA-Z0-9_-]*" => |name: String| match name.as_str() {
+        "let" => Token::Let,
+        "in" => Token::In,
+        _ => Token::Ident(name),
+    },
     r"[+-]?(?:[0-9]+[.])?[0-9]+" => Token::NumLit
 }
 
@@ -322,5 +335,7 @@ mod test {
         "#),
         empty_interpolation   => v!(r#""${}""#),
         safe_interpolation   => v!(r#""${ { "key": "value" }.key1?.key2 ?: "Recovery" }""#),
+        int_div               => v!(r#"7 // 2 / 1"#),
+        let_in_identifiers    => v!(r#"let in-progress = letter-x in in-progress + let-me"#),
     }
 }
@@ -11,6 +11,21 @@ pub fn parse_string(byte_offset: usize, file_id: usize, s: &str) -> Result<Simpl
 
 #[cfg(test)]
 mod tests {
+    /// The `Display` output of an expression has to parse back into the same expression, as it is shown in `eww debug`.
+    #[test]
+    fn test_display_round_trip() {
+        for text in [
+            "let x = foo.bar in x > 1 ? x : 1 + (let y = 2 in y)",
+            "reduce(foo, (a, b) => a * b, 1)",
+            "1 + (let x = 1 in x) * 2",
+            "7 // 2 / 1",
+        ] {
+            let parsed = super::parse_string(0, 0, text).unwrap();
+            let reparsed = super::parse_string(0, 0, &parsed.to_string()).unwrap();
+            assert_eq!(parsed.to_string(), reparsed.to_string());
+        }
+    }
+
     macro_rules! test_parser {
         ($($text:literal),* $(,)?) => {{
             let p = crate::simplexpr_parser::ExprParser::new();
@@ -42,6 +57,9 @@ mod tests {
             r#"[1, 2, 3 + 4, "bla", [blub, blo]]"#,
             r#"{ "key": "value", 5: 1+2, true: false }"#,
             r#"{ "key": "value" }?.key?.does_not_exist"#,
+            "map(foo, x => x.bar)",
+            "reduce(foo, (acc, x) => acc + x, 0)",
+            "let x = foo.bar in x > 1 ? x : 1 + (let y = 2 in y)",
         );
     }
 }
@@ -2,6 +2,7 @@ pub mod attributes;
 pub mod backend_window_options;
 pub mod file_provider;
 pub mod monitor;
+pub mod notification_server_definition;
 pub mod script_var_definition;
 pub mod toplevel;
 pub mod validate;
@@ -0,0 +1,27 @@
+use crate::{
+    error::{DiagResult, DiagResultExt},
+    parser::{ast::Ast, ast_iterator::AstIterator, from_ast::FromAstElementContent},
+};
+use eww_shared_util::Span;
+
+/// Enables eww's own notification server, which publishes received notifications in `EWW_NOTIFICATIONS`.
+#[derive(Debug, PartialEq, Eq, Clone, serde::Serialize)]
+pub struct NotificationServerDefinition {
+    /// Amount of notifications that are kept before the oldest ones are dropped
+    pub history_size: usize,
+    pub span: Span,
+}
+
+impl FromAstElementContent for NotificationServerDefinition {
+    const ELEMENT_NAME: &'static str = "defnotifications";
+
+    fn from_tail<I: Iterator<Item = Ast>>(span: Span, mut iter: AstIterator<I>) -> DiagResult<Self> {
+        let result: DiagResult<_> = (move || {
+            let mut attrs = iter.expect_key_values()?;
+            let history_size = attrs.primitive_optional("history")?.unwrap_or(50);
+            iter.expect_done()?;
+            Ok(Self { history_size, span })
+        })();
+        result.note(r#"Expected format: `(defnotifications :history 50)`"#)
+    }
+}
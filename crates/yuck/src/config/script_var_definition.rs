@@ -1,16 +1,25 @@
 use simplexpr::{SimplExpr, dynval::DynVal};
 
 use crate::{
+    config::window_definition::EnumParseError,
+    enum_parse,
     error::{DiagError, DiagResult, DiagResultExt},
     format_diagnostic::ToDiagnostic,
-    parser::{ast::Ast, ast_iterator::AstIterator, from_ast::FromAstElementContent},
+    gen_diagnostic,
+    parser::{
+        ast::Ast,
+        ast_iterator::AstIterator,
+        from_ast::{FromAst, FromAstElementContent},
+    },
 };
-use eww_shared_util::{Span, VarName};
+use eww_shared_util::{Span, Spanned, VarName};
 
 #[derive(Clone, Debug, PartialEq, Eq, serde::Serialize)]
 pub enum ScriptVarDefinition {
     Poll(PollScriptVar),
     Listen(ListenScriptVar),
+    File(FileVar),
+    Dbus(DbusVar),
 }
 
 impl ScriptVarDefinition {
@@ -18,6 +27,8 @@ impl ScriptVarDefinition {
         match self {
             ScriptVarDefinition::Poll(x) => x.name_span,
             ScriptVarDefinition::Listen(x) => x.name_span,
+            ScriptVarDefinition::File(x) => x.name_span,
+            ScriptVarDefinition::Dbus(x) => x.name_span,
         }
     }
 
@@ -25,16 +36,23 @@ impl ScriptVarDefinition {
         match self {
             ScriptVarDefinition::Poll(x) => &x.name,
             ScriptVarDefinition::Listen(x) => &x.name,
+            ScriptVarDefinition::File(x) => &x.name,
+            ScriptVarDefinition::Dbus(x) => &x.name,
         }
     }
 
     pub fn command_span(&self) -> Option<Span> {
         match self {
-            ScriptVarDefinition::Poll(x) => match x.command {
-                VarSource::Shell(span, ..) => Some(span),
+            ScriptVarDefinition::Poll(x) => match &x.command {
+                VarSource::Shell(span, ..) => Some(*span),
+                VarSource::Expression(expr) => Some(expr.span()),
                 VarSource::Function(_) => None,
             },
-            ScriptVarDefinition::Listen(x) => Some(x.command_span),
+            ScriptVarDefinition::Listen(x) => match x.command {
+                ListenVarSource::Shell(span, ..) => Some(span),
+                ListenVarSource::Function(_) => None,
+            },
+            ScriptVarDefinition::File(_) | ScriptVarDefinition::Dbus(_) => None,
         }
     }
 }
@@ -42,12 +60,56 @@ impl ScriptVarDefinition {
 #[allow(unpredictable_function_pointer_comparisons)]
 #[derive(Clone, Debug, PartialEq, Eq, serde::Serialize)]
 pub enum VarSource {
-    // TODO allow for other executors? (python, etc)
     Shell(Span, String),
+    /// A simplexpr, evaluated in-process with some additional functions, see `:lang`
+    Expression(SimplExpr),
     #[serde(skip)]
     Function(fn() -> Result<DynVal, Box<dyn std::error::Error + Sync + Send + 'static>>),
 }
 
+/// The language the script of a [`PollScriptVar`] is written in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, smart_default::SmartDefault, serde::Serialize)]
+pub enum ScriptLanguage {
+    #[default]
+    Shell,
+    Simplexpr,
+}
+
+impl std::str::FromStr for ScriptLanguage {
+    type Err = EnumParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        enum_parse! { "lang", s,
+            "sh" => ScriptLanguage::Shell,
+            "simplexpr" => ScriptLanguage::Simplexpr,
+        }
+    }
+}
+
+/// What the value of a [`PollScriptVar`] is set to when its script fails.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, smart_default::SmartDefault, serde::Serialize)]
+pub enum PollErrorBehavior {
+    /// Keep the last value
+    #[default]
+    Keep,
+    /// Reset the variable to its initial value
+    Initial,
+    /// Set the variable to its `:error-value`
+    Value,
+}
+
+impl std::str::FromStr for PollErrorBehavior {
+    type Err = EnumParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        enum_parse! { "on-error", s,
+            "keep" => PollErrorBehavior::Keep,
+            "initial" => PollErrorBehavior::Initial,
+            "value" => PollErrorBehavior::Value,
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, serde::Serialize)]
 pub struct PollScriptVar {
     pub name: VarName,
@@ -55,46 +117,201 @@ pub struct PollScriptVar {
     pub command: VarSource,
     pub initial_value: Option<DynVal>,
     pub interval: std::time::Duration,
+    /// Time after which the script is considered failed. Shell scripts are killed, simplexpr scripts are abandoned.
+    pub timeout: Option<std::time::Duration>,
+    pub on_error: PollErrorBehavior,
+    /// Expression that is evaluated in the global scope on errors with [`PollErrorBehavior::Value`]
+    pub error_value: Option<SimplExpr>,
+    /// Paths a [`VarSource::Expression`] may access through its file functions. Access to anything else is denied.
+    pub allowed_paths: Vec<String>,
     pub name_span: Span,
 }
 
+impl PollScriptVar {
+    /// Name of the variable that holds the error of the last poll of this variable, or an empty string if it succeeded.
+    /// Only user-defined variables have one, builtin variables (with a [`VarSource::Function`]) don't.
+    pub fn error_var_name(&self) -> Option<VarName> {
+        match self.command {
+            VarSource::Function(_) => None,
+            _ => Some(VarName(format!("{}__error", self.name))),
+        }
+    }
+}
+
 impl FromAstElementContent for PollScriptVar {
     const ELEMENT_NAME: &'static str = "defpoll";
 
-    fn from_tail<I: Iterator<Item = Ast>>(_span: Span, mut iter: AstIterator<I>) -> DiagResult<Self> {
+    fn from_tail<I: Iterator<Item = Ast>>(span: Span, mut iter: AstIterator<I>) -> DiagResult<Self> {
         let result: DiagResult<_> = (move || {
             let (name_span, name) = iter.expect_symbol()?;
             let mut attrs = iter.expect_key_values()?;
             let initial_value = Some(attrs.primitive_optional("initial")?.unwrap_or_else(|| DynVal::from_string(String::new())));
-            let interval =
-                attrs.primitive_required::<DynVal, _>("interval")?.as_duration().map_err(|e| DiagError(e.to_diagnostic()))?;
-            let (script_span, script) = iter.expect_literal()?;
+            let interval = non_zero_duration("interval", attrs.primitive_required::<DynVal, _>("interval")?)?;
+            let lang = attrs.primitive_optional::<ScriptLanguage, _>("lang")?.unwrap_or_default();
+            let command = match lang {
+                ScriptLanguage::Shell => {
+                    let (script_span, script) = iter.expect_literal()?;
+                    VarSource::Shell(script_span, script.to_string())
+                }
+                ScriptLanguage::Simplexpr => {
+                    VarSource::Expression(iter.expect_any().map_err(DiagError::from).and_then(SimplExpr::from_ast)?)
+                }
+            };
 
             let run_while_expr =
                 attrs.ast_optional::<SimplExpr>("run-while")?.unwrap_or_else(|| SimplExpr::Literal(DynVal::from(true)));
+            let timeout = attrs
+                .primitive_optional::<DynVal, _>("timeout")?
+                .map(|timeout| non_zero_duration("timeout", timeout))
+                .transpose()?;
+            let allowed_paths = match attrs.primitive_optional::<DynVal, _>("allowed-paths")? {
+                Some(_) if lang != ScriptLanguage::Simplexpr => {
+                    return Err(DiagError(gen_diagnostic! {
+                        msg = "`:allowed-paths` can only be used with `:lang \"simplexpr\"`",
+                        label = span,
+                    }));
+                }
+                Some(paths) => paths
+                    .as_json_array()
+                    .map_err(|e| DiagError(e.to_diagnostic()))?
+                    .into_iter()
+                    .map(|path| match path.as_str() {
+                        Some(path) => Ok(path.to_string()),
+                        None => Err(DiagError(gen_diagnostic! {
+                            msg = format!("`:allowed-paths` must be a list of strings, but contains `{}`", path),
+                            label = span,
+                        })),
+                    })
+                    .collect::<DiagResult<Vec<_>>>()?,
+                None => Vec::new(),
+            };
+            let on_error = attrs.primitive_optional::<PollErrorBehavior, _>("on-error")?;
+            let error_value = attrs.ast_optional::<SimplExpr>("error-value")?;
+            // an `:error-value` is only ever used with `:on-error "value"`, so that's implied
+            let on_error = match (on_error, &error_value) {
+                (Some(PollErrorBehavior::Value), None) => {
+                    return Err(DiagError(gen_diagnostic! {
+                        msg = "`:on-error \"value\"` requires an `:error-value`",
+                        label = span,
+                    }));
+                }
+                (Some(on_error), _) => on_error,
+                (None, Some(_)) => PollErrorBehavior::Value,
+                (None, None) => PollErrorBehavior::Keep,
+            };
 
             iter.expect_done()?;
             Ok(Self {
                 name_span,
                 name: VarName(name),
                 run_while_expr,
-                command: VarSource::Shell(script_span, script.to_string()),
+                command,
                 initial_value,
                 interval,
+                timeout,
+                on_error,
+                error_value,
+                allowed_paths,
             })
         })();
-        result.note(r#"Expected format: `(defpoll name :interval "10s" "echo 'a shell script'")`"#)
+        result.note(r#"Expected format: `(defpoll name :interval "10s" "echo 'a shell script'")` or `(defpoll name :interval "10s" :lang "simplexpr" { read_file("/tmp/example") })`"#)
+    }
+}
+
+/// Parse the value of a duration attribute, which must not be zero, as polling with a zero interval would spin.
+fn non_zero_duration(attr_name: &str, value: DynVal) -> DiagResult<std::time::Duration> {
+    let duration = value.as_duration().map_err(|e| DiagError(e.to_diagnostic()))?;
+    if duration.is_zero() {
+        return Err(DiagError(gen_diagnostic! {
+            msg = format!("`:{}` must be greater than zero", attr_name),
+            label = value.span(),
+        }));
+    }
+    Ok(duration)
+}
+
+/// Callback through which a [`ListenVarSource::Function`] publishes new values of its variable.
+pub type ListenVarSender = Box<dyn Fn(DynVal) + Send + Sync + 'static>;
+
+/// Future driving a [`ListenVarSource::Function`]. It is dropped when the variable is stopped.
+pub type ListenVarFuture =
+    std::pin::Pin<Box<dyn std::future::Future<Output = Result<(), Box<dyn std::error::Error + Sync + Send + 'static>>> + Send>>;
+
+#[allow(unpredictable_function_pointer_comparisons)]
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize)]
+pub enum ListenVarSource {
+    Shell(Span, String),
+    #[serde(skip)]
+    Function(fn(ListenVarSender) -> ListenVarFuture),
+}
+
+/// When the script of a [`ListenScriptVar`] is restarted after it exited.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, smart_default::SmartDefault, serde::Serialize)]
+pub enum RestartPolicy {
+    Always,
+    OnFailure,
+    #[default]
+    Never,
+}
+
+impl std::str::FromStr for RestartPolicy {
+    type Err = EnumParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        enum_parse! { "restart", s,
+            "always" => RestartPolicy::Always,
+            "on-failure" => RestartPolicy::OnFailure,
+            "never" => RestartPolicy::Never,
+        }
+    }
+}
+
+/// How the lines output by the script of a [`ListenScriptVar`] update its value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, smart_default::SmartDefault, serde::Serialize)]
+pub enum ListenVarMode {
+    /// Every line replaces the value
+    #[default]
+    Replace,
+    /// Every line is a JSON value that is deep merged into the value
+    JsonMerge,
+    /// Every line is a JSON patch (RFC 6902) that is applied to the value
+    JsonPatch,
+}
+
+impl std::str::FromStr for ListenVarMode {
+    type Err = EnumParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        enum_parse! { "mode", s,
+            "replace" => ListenVarMode::Replace,
+            "json-merge" => ListenVarMode::JsonMerge,
+            "json-patch" => ListenVarMode::JsonPatch,
+        }
     }
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, serde::Serialize)]
 pub struct ListenScriptVar {
     pub name: VarName,
-    pub command: String,
+    pub command: ListenVarSource,
     pub initial_value: DynVal,
-    pub command_span: Span,
+    pub mode: ListenVarMode,
+    pub restart: RestartPolicy,
+    /// Delay before the first restart, which doubles with every consecutive restart
+    pub restart_delay: std::time::Duration,
+    /// Number of consecutive restarts after which the script is given up on
+    pub max_retries: Option<u32>,
     pub name_span: Span,
 }
+
+impl ListenScriptVar {
+    pub const DEFAULT_RESTART_DELAY: std::time::Duration = std::time::Duration::from_secs(1);
+
+    /// Name of the variable that holds the status of the script of this variable.
+    pub fn status_var_name(&self) -> VarName {
+        VarName(format!("{}__status", self.name))
+    }
+}
 impl FromAstElementContent for ListenScriptVar {
     const ELEMENT_NAME: &'static str = "deflisten";
 
@@ -102,11 +319,217 @@ impl FromAstElementContent for ListenScriptVar {
         let result: DiagResult<_> = (move || {
             let (name_span, name) = iter.expect_symbol()?;
             let mut attrs = iter.expect_key_values()?;
-            let initial_value = attrs.primitive_optional("initial")?.unwrap_or_else(|| DynVal::from_string(String::new()));
+            let initial_value = attrs.primitive_optional::<DynVal, _>("initial")?;
+            let mode = attrs.primitive_optional::<ListenVarMode, _>("mode")?.unwrap_or_default();
+            let initial_value = match initial_value {
+                Some(initial_value) if mode != ListenVarMode::Replace => {
+                    if let Err(err) = initial_value.as_json_value() {
+                        return Err(DiagError(gen_diagnostic! {
+                            msg = "`:initial` must be valid JSON when `:mode` is `json-merge` or `json-patch`",
+                            label = initial_value.span(),
+                            note = err.to_string(),
+                        }));
+                    }
+                    initial_value
+                }
+                Some(initial_value) => initial_value,
+                // the deltas of the json modes are applied to an empty object by default
+                None if mode != ListenVarMode::Replace => DynVal::from_string("{}".to_string()),
+                None => DynVal::from_string(String::new()),
+            };
+            let restart = attrs.primitive_optional::<RestartPolicy, _>("restart")?.unwrap_or_default();
+            let restart_delay = attrs
+                .primitive_optional::<DynVal, _>("restart-delay")?
+                .map(|delay| delay.as_duration().map_err(|e| DiagError(e.to_diagnostic())))
+                .transpose()?
+                .unwrap_or(Self::DEFAULT_RESTART_DELAY);
+            let max_retries = attrs.primitive_optional::<u32, _>("max-retries")?;
+            if let Some(lang) = attrs.primitive_optional::<DynVal, _>("lang")? {
+                return Err(DiagError(gen_diagnostic! {
+                    msg = "`:lang` is only supported by `defpoll`",
+                    label = lang.span(),
+                    note = "The script of a `deflisten` is always run with `sh`",
+                }));
+            }
             let (command_span, script) = iter.expect_literal()?;
             iter.expect_done()?;
-            Ok(Self { name_span, name: VarName(name), command: script.to_string(), initial_value, command_span })
+            Ok(Self {
+                name_span,
+                name: VarName(name),
+                command: ListenVarSource::Shell(command_span, script.to_string()),
+                initial_value,
+                mode,
+                restart,
+                restart_delay,
+                max_retries,
+            })
+        })();
+        result.note(
+            r#"Expected format: `(deflisten name :initial "0" :restart "on-failure" :restart-delay "1s" "tail -f /tmp/example")`"#,
+        )
+    }
+}
+
+/// How the contents of a [`FileVar`] are turned into the value of the variable.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, smart_default::SmartDefault, serde::Serialize)]
+pub enum FileVarFormat {
+    /// The contents as a string, without trailing newlines
+    #[default]
+    Text,
+    /// The contents, which must be valid JSON
+    Json,
+    /// A JSON array of the lines of the file
+    Lines,
+}
+
+impl std::str::FromStr for FileVarFormat {
+    type Err = EnumParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        enum_parse! { "format", s,
+            "text" => FileVarFormat::Text,
+            "json" => FileVarFormat::Json,
+            "lines" => FileVarFormat::Lines,
+        }
+    }
+}
+
+/// A variable holding the contents of a file, updated whenever that file changes.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize)]
+pub struct FileVar {
+    pub name: VarName,
+    pub path: String,
+    pub format: FileVarFormat,
+    /// Used while the file doesn't exist or can't be read
+    pub initial_value: DynVal,
+    pub name_span: Span,
+}
+
+impl FromAstElementContent for FileVar {
+    const ELEMENT_NAME: &'static str = "deffile";
+
+    fn from_tail<I: Iterator<Item = Ast>>(_span: Span, mut iter: AstIterator<I>) -> DiagResult<Self> {
+        let result: DiagResult<_> = (move || {
+            let (name_span, name) = iter.expect_symbol()?;
+            let mut attrs = iter.expect_key_values()?;
+            let path = attrs.primitive_required::<String, _>("path")?;
+            let format = attrs.primitive_optional("format")?.unwrap_or_default();
+            let initial_value = attrs.primitive_optional("initial")?.unwrap_or_else(|| DynVal::from_string(String::new()));
+            iter.expect_done()?;
+            Ok(Self { name: VarName(name), path, format, initial_value, name_span })
+        })();
+        result.note(r#"Expected format: `(deffile name :path "/tmp/example" :format "text")`"#)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, smart_default::SmartDefault, serde::Serialize)]
+pub enum DbusBus {
+    #[default]
+    Session,
+    System,
+}
+
+impl std::str::FromStr for DbusBus {
+    type Err = EnumParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        enum_parse! { "bus", s,
+            "session" => DbusBus::Session,
+            "system" => DbusBus::System,
+        }
+    }
+}
+
+/// What a [`DbusVar`] is bound to.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize)]
+pub enum DbusVarSource {
+    /// The value of a property, kept up to date through `PropertiesChanged`
+    Property(String),
+    /// The arguments of the last received signal with this name, as a JSON array
+    Signal(String),
+}
+
+/// A variable bound to a D-Bus property or signal.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize)]
+pub struct DbusVar {
+    pub name: VarName,
+    pub bus: DbusBus,
+    /// Bus name of the service. Optional for signals, in which case signals from any sender are received
+    pub destination: Option<String>,
+    pub path: String,
+    pub interface: String,
+    pub source: DbusVarSource,
+    pub initial_value: DynVal,
+    pub name_span: Span,
+}
+
+impl FromAstElementContent for DbusVar {
+    const ELEMENT_NAME: &'static str = "defdbus";
+
+    fn from_tail<I: Iterator<Item = Ast>>(span: Span, mut iter: AstIterator<I>) -> DiagResult<Self> {
+        let result: DiagResult<_> = (move || {
+            let (name_span, name) = iter.expect_symbol()?;
+            let mut attrs = iter.expect_key_values()?;
+            let bus = attrs.primitive_optional("bus")?.unwrap_or_default();
+            let destination = attrs.primitive_optional::<String, _>("destination")?;
+            let path = attrs.primitive_required::<String, _>("path")?;
+            let interface = attrs.primitive_required::<String, _>("interface")?;
+            let property = attrs.primitive_optional::<String, _>("property")?;
+            let signal = attrs.primitive_optional::<String, _>("signal")?;
+            let initial_value = attrs.primitive_optional("initial")?.unwrap_or_else(|| DynVal::from_string(String::new()));
+            iter.expect_done()?;
+
+            let source = match (property, signal) {
+                (Some(property), None) if destination.is_some() => DbusVarSource::Property(property),
+                (Some(_), None) => {
+                    return Err(DiagError(gen_diagnostic! {
+                        msg = "A D-Bus property variable needs a `:destination`",
+                        label = span,
+                    }));
+                }
+                (None, Some(signal)) => DbusVarSource::Signal(signal),
+                _ => {
+                    return Err(DiagError(gen_diagnostic! {
+                        msg = "A D-Bus variable needs exactly one of `:property` or `:signal`",
+                        label = span,
+                    }));
+                }
+            };
+            Ok(Self { name: VarName(name), bus, destination, path, interface, source, initial_value, name_span })
+        })();
+        result.note(
+            r#"Expected format: `(defdbus name :bus "system" :destination "org.freedesktop.UPower" :path "/org/freedesktop/UPower" :interface "org.freedesktop.UPower" :property "OnBattery")`"#,
+        )
+    }
+}
+
+/// Overrides the interval and run-while condition of one of eww's builtin magic variables,
+/// and allows switching some of them back to the format they had in older versions of eww.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize)]
+pub struct BuiltinVarOverride {
+    pub name: VarName,
+    pub interval: Option<std::time::Duration>,
+    pub run_while_expr: Option<SimplExpr>,
+    pub legacy_keys: bool,
+    pub name_span: Span,
+}
+
+impl FromAstElementContent for BuiltinVarOverride {
+    const ELEMENT_NAME: &'static str = "defbuiltin";
+
+    fn from_tail<I: Iterator<Item = Ast>>(_span: Span, mut iter: AstIterator<I>) -> DiagResult<Self> {
+        let result: DiagResult<_> = (move || {
+            let (name_span, name) = iter.expect_symbol()?;
+            let mut attrs = iter.expect_key_values()?;
+            let interval = attrs
+                .primitive_optional::<DynVal, _>("interval")?
+                .map(|interval| interval.as_duration().map_err(|e| DiagError(e.to_diagnostic())))
+                .transpose()?;
+            let run_while_expr = attrs.ast_optional::<SimplExpr>("run-while")?;
+            let legacy_keys = attrs.primitive_optional("legacy-keys")?.unwrap_or(false);
+            iter.expect_done()?;
+            Ok(Self { name_span, name: VarName(name), interval, run_while_expr, legacy_keys })
         })();
-        result.note(r#"Expected format: `(deflisten name :initial "0" "tail -f /tmp/example")`"#)
+        result.note(r#"Expected format: `(defbuiltin EWW_CPU :interval "500ms" :run-while cpu-visible)`"#)
     }
 }
@@ -7,13 +7,14 @@ use itertools::Itertools;
 
 use super::{
     file_provider::{FilesError, YuckFileProvider},
-    script_var_definition::ScriptVarDefinition,
+    notification_server_definition::NotificationServerDefinition,
+    script_var_definition::{BuiltinVarOverride, ScriptVarDefinition},
     var_definition::VarDefinition,
     widget_definition::WidgetDefinition,
     window_definition::WindowDefinition,
 };
 use crate::{
-    config::script_var_definition::{ListenScriptVar, PollScriptVar},
+    config::script_var_definition::{DbusVar, FileVar, ListenScriptVar, PollScriptVar},
     error::{DiagError, DiagResult},
     gen_diagnostic,
     parser::{
@@ -30,6 +31,10 @@ static TOP_LEVEL_DEFINITION_NAMES: &[&str] = &[
     VarDefinition::ELEMENT_NAME,
     ListenScriptVar::ELEMENT_NAME,
     PollScriptVar::ELEMENT_NAME,
+    FileVar::ELEMENT_NAME,
+    DbusVar::ELEMENT_NAME,
+    BuiltinVarOverride::ELEMENT_NAME,
+    NotificationServerDefinition::ELEMENT_NAME,
     Include::ELEMENT_NAME,
 ];
 
@@ -54,6 +59,8 @@ pub enum TopLevel {
     Include(Include),
     VarDefinition(VarDefinition),
     ScriptVarDefinition(ScriptVarDefinition),
+    BuiltinVarOverride(BuiltinVarOverride),
+    NotificationServerDefinition(NotificationServerDefinition),
     WidgetDefinition(WidgetDefinition),
     WindowDefinition(WindowDefinition),
 }
@@ -73,6 +80,16 @@ impl FromAst for TopLevel {
             x if x == ListenScriptVar::ELEMENT_NAME => {
                 Self::ScriptVarDefinition(ScriptVarDefinition::Listen(ListenScriptVar::from_tail(span, iter)?))
             }
+            x if x == FileVar::ELEMENT_NAME => {
+                Self::ScriptVarDefinition(ScriptVarDefinition::File(FileVar::from_tail(span, iter)?))
+            }
+            x if x == DbusVar::ELEMENT_NAME => {
+                Self::ScriptVarDefinition(ScriptVarDefinition::Dbus(DbusVar::from_tail(span, iter)?))
+            }
+            x if x == BuiltinVarOverride::ELEMENT_NAME => Self::BuiltinVarOverride(BuiltinVarOverride::from_tail(span, iter)?),
+            x if x == NotificationServerDefinition::ELEMENT_NAME => {
+                Self::NotificationServerDefinition(NotificationServerDefinition::from_tail(span, iter)?)
+            }
             x if x == WindowDefinition::ELEMENT_NAME => Self::WindowDefinition(WindowDefinition::from_tail(span, iter)?),
             x => {
                 return Err(DiagError(gen_diagnostic! {
@@ -91,6 +108,8 @@ pub struct Config {
     pub window_definitions: HashMap<String, WindowDefinition>,
     pub var_definitions: HashMap<VarName, VarDefinition>,
     pub script_vars: HashMap<VarName, ScriptVarDefinition>,
+    pub builtin_var_overrides: HashMap<VarName, BuiltinVarOverride>,
+    pub notification_server: Option<NotificationServerDefinition>,
 }
 
 impl Config {
@@ -116,6 +135,26 @@ impl Config {
                     self.script_vars.insert(x.name().clone(), x);
                 }
             }
+            TopLevel::BuiltinVarOverride(x) => {
+                if self.builtin_var_overrides.contains_key(&x.name) {
+                    return Err(DiagError(gen_diagnostic! {
+                        msg = format!("Builtin variable {} overridden twice", x.name),
+                        label = x.name_span => "overridden again here",
+                    }));
+                } else {
+                    self.builtin_var_overrides.insert(x.name.clone(), x);
+                }
+            }
+            TopLevel::NotificationServerDefinition(x) => {
+                if self.notification_server.is_some() {
+                    return Err(DiagError(gen_diagnostic! {
+                        msg = "Notification server defined twice",
+                        label = x.span => "defined again here",
+                    }));
+                } else {
+                    self.notification_server = Some(x);
+                }
+            }
             TopLevel::WidgetDefinition(x) => {
                 self.widget_definitions.insert(x.name.clone(), x);
             }
@@ -144,6 +183,8 @@ impl Config {
             window_definitions: HashMap::new(),
             var_definitions: HashMap::new(),
             script_vars: HashMap::new(),
+            builtin_var_overrides: HashMap::new(),
+            notification_server: None,
         };
         for element in elements {
             config.append_toplevel(files, TopLevel::from_ast(element)?)?;
@@ -159,3 +200,96 @@ impl Config {
         Self::generate(files, top_levels)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::script_var_definition::{DbusBus, DbusVarSource};
+
+    /// Provides the given source as the main file, without support for includes.
+    struct TestFileProvider;
+
+    impl YuckFileProvider for TestFileProvider {
+        fn load_yuck_file(&mut self, path: PathBuf) -> Result<(Span, Vec<Ast>), FilesError> {
+            panic!("Tried to include {}", path.display())
+        }
+
+        fn load_yuck_str(&mut self, _name: String, content: String) -> Result<(Span, Vec<Ast>), DiagError> {
+            crate::parser::parse_toplevel(0, content)
+        }
+
+        fn unload(&mut self, _id: usize) {}
+    }
+
+    fn generate(source: &str) -> DiagResult<Config> {
+        let mut files = TestFileProvider;
+        let (_span, elements) = files.load_yuck_str("config.yuck".to_string(), source.to_string())?;
+        Config::generate(&mut files, elements)
+    }
+
+    #[test]
+    fn test_defdbus() {
+        let config = generate(
+            r#"
+            (defdbus on-battery :bus "system"
+                                :destination "org.freedesktop.UPower"
+                                :path "/org/freedesktop/UPower"
+                                :interface "org.freedesktop.UPower"
+                                :property "OnBattery")
+            (defdbus last-job :path "/org/freedesktop/systemd1"
+                              :interface "org.freedesktop.systemd1.Manager"
+                              :signal "JobRemoved"
+                              :initial "[]")
+            "#,
+        )
+        .unwrap();
+
+        let Some(ScriptVarDefinition::Dbus(on_battery)) = config.script_vars.get(&VarName::from("on-battery")) else {
+            panic!("on-battery is not a D-Bus variable");
+        };
+        assert_eq!(on_battery.bus, DbusBus::System);
+        assert_eq!(on_battery.destination.as_deref(), Some("org.freedesktop.UPower"));
+        assert_eq!(on_battery.path, "/org/freedesktop/UPower");
+        assert_eq!(on_battery.interface, "org.freedesktop.UPower");
+        assert_eq!(on_battery.source, DbusVarSource::Property("OnBattery".to_string()));
+
+        let Some(ScriptVarDefinition::Dbus(last_job)) = config.script_vars.get(&VarName::from("last-job")) else {
+            panic!("last-job is not a D-Bus variable");
+        };
+        assert_eq!(last_job.bus, DbusBus::Session);
+        assert_eq!(last_job.destination, None);
+        assert_eq!(last_job.source, DbusVarSource::Signal("JobRemoved".to_string()));
+        assert_eq!(last_job.initial_value, simplexpr::dynval::DynVal::from("[]"));
+
+        assert!(generate(r#"(defdbus foo :path "/" :interface "a.b" :property "Foo")"#).is_err());
+        assert!(generate(r#"(defdbus foo :destination "a.b" :path "/" :interface "a.b")"#).is_err());
+    }
+
+    #[test]
+    fn test_defpoll_script() {
+        let validate = |source: &str| crate::config::validate::validate(&generate(source).unwrap(), Vec::new());
+        assert!(validate(r#"(defpoll foo :interval "1s" :lang "simplexpr" { let x = read_file("/a") in x + 1 })"#).is_ok());
+        assert!(matches!(
+            validate(r#"(defvar bar 1) (defpoll foo :interval "1s" :lang "simplexpr" { read_file("/a") + bar })"#),
+            Err(crate::config::validate::ValidationError::VariableInScript { .. })
+        ));
+        assert!(validate(r#"(defvar fallback "N/A") (defpoll foo :interval "1s" :error-value fallback "echo 1")"#).is_ok());
+        assert!(matches!(
+            validate(r#"(defpoll foo :interval "1s" :error-value "${missing}!" "echo 1")"#),
+            Err(crate::config::validate::ValidationError::UnknownVariable { .. })
+        ));
+
+        let config =
+            generate(r#"(defpoll foo :interval "1s" :lang "simplexpr" :allowed-paths '["/sys", "~/.cache"]' 1)"#).unwrap();
+        let Some(ScriptVarDefinition::Poll(foo)) = config.script_vars.get(&VarName::from("foo")) else {
+            panic!("foo is not a poll variable");
+        };
+        assert_eq!(foo.allowed_paths, vec!["/sys".to_string(), "~/.cache".to_string()]);
+        assert!(generate(r#"(defpoll foo :interval "1s" :allowed-paths '["/sys"]' "echo 1")"#).is_err());
+        assert!(generate(r#"(defpoll foo :interval "0s" "echo 1")"#).is_err());
+        assert!(generate(r#"(deflisten foo :lang "simplexpr" "tail -f /tmp/foo")"#).is_err());
+        assert!(generate(r#"(deflisten foo :mode "json-merge" :initial "{" "tail -f /tmp/foo")"#).is_err());
+        assert!(generate(r#"(deflisten foo :mode "json-patch" :initial "[1]" "tail -f /tmp/foo")"#).is_ok());
+        assert!(generate(r#"(defpoll foo :interval "1s" :timeout "0ms" "echo 1")"#).is_err());
+    }
+}
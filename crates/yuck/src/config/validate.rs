@@ -2,7 +2,12 @@ use std::collections::{HashMap, HashSet};
 
 use simplexpr::SimplExpr;
 
-use super::{Config, widget_definition::WidgetDefinition, widget_use::WidgetUse};
+use super::{
+    Config,
+    script_var_definition::{ScriptVarDefinition, VarSource},
+    widget_definition::WidgetDefinition,
+    widget_use::WidgetUse,
+};
 use eww_shared_util::{AttrName, Span, Spanned, VarName};
 
 #[derive(Debug, thiserror::Error)]
@@ -10,6 +15,12 @@ pub enum ValidationError {
     #[error("There is already a builtin widget called `{1}`")]
     AccidentalBuiltinOverride(Span, String),
 
+    #[error("There is no polled builtin variable called `{1}`")]
+    UnknownBuiltinVar(Span, VarName),
+
+    #[error("The builtin variable `{1}` has no legacy format")]
+    NoLegacyKeys(Span, VarName),
+
     #[error("Missing attribute `{arg_name}` in use of widget `{widget_name}`")]
     MissingAttr { widget_name: String, arg_name: AttrName, arg_list_span: Option<Span>, use_span: Span },
 
@@ -20,6 +31,9 @@ pub enum ValidationError {
         /// True if the error occurred inside a widget definition, false if it occurred in a window definition
         in_definition: bool,
     },
+
+    #[error("The `:lang \"simplexpr\"` script of `{script_var}` references the variable `{name}`")]
+    VariableInScript { span: Span, name: VarName, script_var: VarName },
 }
 
 impl Spanned for ValidationError {
@@ -28,6 +42,9 @@ impl Spanned for ValidationError {
             ValidationError::MissingAttr { use_span, .. } => *use_span,
             ValidationError::UnknownVariable { span, .. } => *span,
             ValidationError::AccidentalBuiltinOverride(span, ..) => *span,
+            ValidationError::UnknownBuiltinVar(span, ..) => *span,
+            ValidationError::NoLegacyKeys(span, ..) => *span,
+            ValidationError::VariableInScript { span, .. } => *span,
         }
     }
 }
@@ -48,6 +65,19 @@ pub fn validate(config: &Config, additional_globals: Vec<VarName>) -> Result<(),
     for def in config.widget_definitions.values() {
         validate_widget_definition(&config.widget_definitions, &var_names, def)?;
     }
+    for var in config.script_vars.values() {
+        let ScriptVarDefinition::Poll(var) = var else { continue };
+        if let VarSource::Expression(expr) = &var.command
+            && let Some((span, name)) = expr.var_refs_with_span().into_iter().next()
+        {
+            return Err(ValidationError::VariableInScript { span, name: name.clone(), script_var: var.name.clone() });
+        }
+        let unknown_var =
+            var.error_value.iter().flat_map(|expr| expr.var_refs_with_span()).find(|(_, name)| !var_names.contains(*name));
+        if let Some((span, name)) = unknown_var {
+            return Err(ValidationError::UnknownVariable { span, name: name.clone(), in_definition: false });
+        }
+    }
     Ok(())
 }
 
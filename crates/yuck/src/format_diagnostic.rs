@@ -122,6 +122,11 @@ impl ToDiagnostic for ValidationError {
                 }
                 diag
             }
+            ValidationError::VariableInScript { span, .. } => gen_diagnostic! {
+                msg = self,
+                label = span => "Used here",
+                note = "Hint: Scripts are evaluated on their own and can not access any variables. Use `let` to define local values",
+            },
             ValidationError::UnknownVariable { span, name, in_definition } => {
                 let diag = gen_diagnostic! {
                     msg = self,
@@ -147,6 +152,16 @@ impl ToDiagnostic for ValidationError {
                 label = span => "Defined here",
                 note = "Hint: Give your widget a different name. You could call it \"John\" for example. That's a cool name."
             },
+            ValidationError::UnknownBuiltinVar(span, _var_name) => gen_diagnostic! {
+                msg = self,
+                label = span => "Overridden here",
+                note = "Hint: `defbuiltin` can only change the polling of the magic variables listed in the documentation"
+            },
+            ValidationError::NoLegacyKeys(span, _var_name) => gen_diagnostic! {
+                msg = self,
+                label = span => "`:legacy-keys` set here",
+                note = "Hint: Only `EWW_GPU` and `EWW_TEMPS` support `:legacy-keys`"
+            },
         }
     }
 }